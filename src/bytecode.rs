@@ -0,0 +1,361 @@
+//! A compact, resolved binary form of a parsed program.
+//!
+//! Layout (all integers little endian `u64` unless noted):
+//! ```text
+//! magic      b"M8B"
+//! version    u8
+//! count      number of statements
+//! lines      number of source lines
+//! count * (opcode: u8, operands..., span)
+//! ```
+//! Jump targets are stored as resolved statement indices, so no labels remain.
+
+use crate::parse;
+use crate::parse::{Register, Span, Stmt, StmtIdx};
+
+const MAGIC: &[u8; 3] = b"M8B";
+const VERSION: u8 = 1;
+
+/// The most source lines a bytecode file can claim. `render` allocates every line, so a corrupt
+/// count must not be trusted, and programs are far smaller than this
+const MAX_LINE_COUNT: usize = 1 << 20;
+
+const OP_INC: u8 = 0;
+const OP_DEC: u8 = 1;
+const OP_IS_ZERO: u8 = 2;
+const OP_JUMP: u8 = 3;
+const OP_STOP: u8 = 4;
+const OP_DEC_BY: u8 = 5;
+const OP_CALL: u8 = 6;
+const OP_RET: u8 = 7;
+const OP_IS_NOT_ZERO: u8 = 8;
+const OP_IS_EQ: u8 = 9;
+const OP_SWAP: u8 = 10;
+const OP_RAND: u8 = 11;
+const OP_READ: u8 = 12;
+
+/// A program loaded from bytecode. There is no source text, so `code_lines` is reconstructed
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub stmts: Vec<Stmt>,
+    pub span: Vec<Span>,
+    pub line_count: usize,
+}
+
+pub fn encode(stmts: &[Stmt], span: &[Span], line_count: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    push_u64(&mut out, stmts.len());
+    push_u64(&mut out, line_count);
+
+    for (stmt, span) in stmts.iter().zip(span) {
+        match *stmt {
+            Stmt::Inc(r) => {
+                out.push(OP_INC);
+                push_u64(&mut out, r.0);
+            }
+            Stmt::Dec(r) => {
+                out.push(OP_DEC);
+                push_u64(&mut out, r.0);
+            }
+            Stmt::DecBy(r, value) => {
+                out.push(OP_DEC_BY);
+                push_u64(&mut out, r.0);
+                push_u64(&mut out, value);
+            }
+            Stmt::IsZero(r, target) => {
+                out.push(OP_IS_ZERO);
+                push_u64(&mut out, r.0);
+                push_u64(&mut out, target.0);
+            }
+            Stmt::IsNotZero(r, target) => {
+                out.push(OP_IS_NOT_ZERO);
+                push_u64(&mut out, r.0);
+                push_u64(&mut out, target.0);
+            }
+            Stmt::Rand(r, max) => {
+                out.push(OP_RAND);
+                push_u64(&mut out, r.0);
+                push_u64(&mut out, max);
+            }
+            Stmt::Read(r) => {
+                out.push(OP_READ);
+                push_u64(&mut out, r.0);
+            }
+            Stmt::Swap(a, b) => {
+                out.push(OP_SWAP);
+                push_u64(&mut out, a.0);
+                push_u64(&mut out, b.0);
+            }
+            Stmt::IsEq(r, value, target) => {
+                out.push(OP_IS_EQ);
+                push_u64(&mut out, r.0);
+                push_u64(&mut out, value);
+                push_u64(&mut out, target.0);
+            }
+            Stmt::Jump(target) => {
+                out.push(OP_JUMP);
+                push_u64(&mut out, target.0);
+            }
+            Stmt::Call(target) => {
+                out.push(OP_CALL);
+                push_u64(&mut out, target.0);
+            }
+            Stmt::Ret => out.push(OP_RET),
+            Stmt::Stop => out.push(OP_STOP),
+        }
+        push_u64(&mut out, span.0);
+    }
+
+    out
+}
+
+/// Errors count lines from `line_base`
+pub fn decode(bytes: &[u8], line_base: usize) -> Result<Bytecode, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("error: Not an m8db bytecode file.".to_owned());
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(format!(
+            "error: Unsupported bytecode version '{}', expected '{}'.",
+            version, VERSION
+        ));
+    }
+
+    let count = reader.usize()?;
+    let line_count = reader.usize()?;
+    if line_count > MAX_LINE_COUNT {
+        return Err(format!(
+            "error: Bytecode has {} lines, more than the maximum of {}.",
+            line_count, MAX_LINE_COUNT
+        ));
+    }
+    let mut stmts = Vec::new();
+    let mut span = Vec::new();
+
+    for _ in 0..count {
+        let stmt = match reader.u8()? {
+            OP_INC => Stmt::Inc(Register(reader.usize()?)),
+            OP_DEC => Stmt::Dec(Register(reader.usize()?)),
+            OP_DEC_BY => Stmt::DecBy(Register(reader.usize()?), reader.usize()?),
+            OP_IS_ZERO => Stmt::IsZero(Register(reader.usize()?), StmtIdx(reader.usize()?)),
+            OP_IS_NOT_ZERO => Stmt::IsNotZero(Register(reader.usize()?), StmtIdx(reader.usize()?)),
+            OP_IS_EQ => Stmt::IsEq(
+                Register(reader.usize()?),
+                reader.usize()?,
+                StmtIdx(reader.usize()?),
+            ),
+            OP_SWAP => Stmt::Swap(Register(reader.usize()?), Register(reader.usize()?)),
+            OP_RAND => match (Register(reader.usize()?), reader.usize()?) {
+                (_, 0) => return Err("error: RAND with a maximum of zero in bytecode.".to_owned()),
+                (r, max) => Stmt::Rand(r, max),
+            },
+            OP_READ => Stmt::Read(Register(reader.usize()?)),
+            OP_JUMP => Stmt::Jump(StmtIdx(reader.usize()?)),
+            OP_CALL => Stmt::Call(StmtIdx(reader.usize()?)),
+            OP_RET => Stmt::Ret,
+            OP_STOP => Stmt::Stop,
+            op => return Err(format!("error: Unknown opcode '{}' in bytecode.", op)),
+        };
+        let line = reader.usize()?;
+        if line >= line_count {
+            return Err(format!(
+                "error: Statement {} is on line index {}, but the bytecode has {} lines.",
+                stmts.len(),
+                line,
+                line_count
+            ));
+        }
+        stmts.push(stmt);
+        span.push(Span(line));
+    }
+
+    if reader.pos != bytes.len() {
+        return Err("error: Trailing data after bytecode.".to_owned());
+    }
+    if stmts.is_empty() {
+        return Err(parse::NO_STATEMENTS.to_owned());
+    }
+    parse::validate_targets(&stmts, &span, line_base)?;
+
+    Ok(Bytecode {
+        stmts,
+        span,
+        line_count,
+    })
+}
+
+/// The label `render` puts after the last line for jumps to the end of the program
+const END_LABEL: &str = "end";
+
+/// Reconstructs source text for the program, each statement on its original line.
+/// Jump targets are written as line numbers, and jumps to the end of the program as jumps to a
+/// label after the last line, so the result parses to the same program.
+pub fn render(bytecode: &Bytecode) -> String {
+    let line_count = bytecode
+        .span
+        .iter()
+        .map(|span| span.0 + 1)
+        .max()
+        .unwrap_or(0)
+        .max(bytecode.line_count);
+    let mut lines = vec![String::new(); line_count];
+
+    // a jump to a line goes to its first statement, the others on the line need a relative target
+    let target_line = |stmt: usize, target: StmtIdx| match bytecode.span.get(target.0) {
+        Some(span) if target.0 > 0 && bytecode.span[target.0 - 1] == *span => {
+            format!("{:+}", target.0 as isize - stmt as isize)
+        }
+        Some(span) => span.line_number(parse::DEFAULT_LINE_BASE).to_string(),
+        None => END_LABEL.to_owned(),
+    };
+
+    for (i, (stmt, span)) in bytecode.stmts.iter().zip(&bytecode.span).enumerate() {
+        let target_line = |target| target_line(i, target);
+        let rendered = match *stmt {
+            Stmt::Inc(r) => format!("INC {}", r.0),
+            Stmt::Dec(r) => format!("DEC {}", r.0),
+            Stmt::DecBy(r, value) => format!("DEC {} {}", r.0, value),
+            Stmt::IsZero(r, target) => format!("IS_ZERO {} {}", r.0, target_line(target)),
+            Stmt::IsNotZero(r, target) => {
+                format!("IS_NOT_ZERO {} {}", r.0, target_line(target))
+            }
+            Stmt::IsEq(r, value, target) => {
+                format!("IS_EQ {} {} {}", r.0, value, target_line(target))
+            }
+            Stmt::Swap(a, b) => format!("SWAP {} {}", a.0, b.0),
+            Stmt::Rand(r, max) => format!("RAND {} {}", r.0, max),
+            Stmt::Read(r) => format!("READ {}", r.0),
+            Stmt::Jump(target) => format!("JUMP {}", target_line(target)),
+            Stmt::Call(target) => format!("CALL {}", target_line(target)),
+            Stmt::Ret => "RET".to_owned(),
+            Stmt::Stop => "STOP".to_owned(),
+        };
+        let line = &mut lines[span.0];
+        if !line.is_empty() {
+            line.push_str(&format!(" {} ", parse::STMT_DELIMITER));
+        }
+        line.push_str(&rendered);
+    }
+    let jumps_to_end = bytecode
+        .stmts
+        .iter()
+        .filter_map(Stmt::target)
+        .any(|target| target.0 >= bytecode.stmts.len());
+    if jumps_to_end {
+        lines.push(format!(".{}", END_LABEL));
+    }
+
+    lines.join("\n")
+}
+
+fn push_u64(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u64).to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + len;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or_else(|| "error: Unexpected end of bytecode.".to_owned())?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn usize(&mut self) -> Result<usize, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn parse_ok(text: &str) -> parse::Code<'_> {
+        parse::parse(
+            text,
+            "test".to_owned(),
+            parse::DEFAULT_MAX_REGISTER,
+            parse::DEFAULT_LINE_BASE,
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn decode_reverses_encode() {
+        let code = parse_ok("INC 0\n.loop\nIS_ZERO 0 end\nDEC 0\nJUMP loop\n\n.end\nSTOP\n");
+        let bytecode = decode(
+            &encode(&code.stmts, &code.span, code.code_lines.len()),
+            parse::DEFAULT_LINE_BASE,
+        )
+        .unwrap();
+        assert_eq!(bytecode.stmts, code.stmts);
+        assert_eq!(bytecode.span, code.span);
+        assert_eq!(bytecode.line_count, code.code_lines.len());
+    }
+
+    #[test]
+    fn render_parses_to_the_same_program() {
+        // the IS_ZERO jumps past the last statement, to the end of the program
+        let code = parse_ok("INC 0\n\n.loop\nIS_ZERO 0 end\nDEC 0\nJUMP loop\n.end\n");
+        let bytecode = decode(
+            &encode(&code.stmts, &code.span, code.code_lines.len()),
+            parse::DEFAULT_LINE_BASE,
+        )
+        .unwrap();
+        let rendered = render(&bytecode);
+        assert!(rendered.ends_with("\n.end"), "{}", rendered);
+        let reparsed = parse_ok(&rendered);
+        assert_eq!(reparsed.stmts, code.stmts);
+        assert_eq!(reparsed.span, code.span);
+    }
+
+    #[test]
+    fn invalid_line_count_and_span_are_rejected() {
+        // the line count comes after the magic, the version and the statement count
+        let mut bytes = encode(&[Stmt::Stop], &[Span(0)], 1);
+        bytes[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+        let error = decode(&bytes, parse::DEFAULT_LINE_BASE).unwrap_err();
+        assert!(error.contains("more than the maximum"), "{}", error);
+
+        // the span is the last field
+        let mut bytes = encode(&[Stmt::Stop], &[Span(0)], 1);
+        let len = bytes.len();
+        bytes[len - 8..].copy_from_slice(&u64::MAX.to_le_bytes());
+        let error = decode(&bytes, parse::DEFAULT_LINE_BASE).unwrap_err();
+        assert!(error.contains("but the bytecode has 1 lines"), "{}", error);
+    }
+
+    #[test]
+    fn render_keeps_statements_that_share_a_line() {
+        let code = parse_ok("INC 0 | .inner DEC 0\nIS_NOT_ZERO 0 inner | JUMP end\n.end\n");
+        let bytecode = decode(
+            &encode(&code.stmts, &code.span, code.code_lines.len()),
+            parse::DEFAULT_LINE_BASE,
+        )
+        .unwrap();
+        let rendered = render(&bytecode);
+        let reparsed = parse_ok(&rendered);
+        assert_eq!(reparsed.stmts, code.stmts, "{}", rendered);
+        assert_eq!(reparsed.span, code.span);
+    }
+}
@@ -0,0 +1,120 @@
+//! Export of a program's control flow graph in the Graphviz DOT format.
+
+use crate::parse::{Span, Stmt};
+use std::fmt::Write;
+
+/// Renders one node per statement, with edges for fall-through, `JUMP` and both branches of `IS_ZERO`,
+/// `IS_NOT_ZERO` and `IS_EQ`.
+/// `CALL` has an edge to the subroutine and one to where it returns to, `RET` has no edges since
+/// its target depends on the call stack. Edges leaving the program point to a separate `end` node.
+/// Line numbers count from `line_base`.
+pub fn control_flow_graph(stmts: &[Stmt], span: &[Span], line_base: usize) -> String {
+    let mut out = String::from("digraph program {\n    node [shape=box];\n");
+    let mut has_end = false;
+
+    let mut node = |idx: usize| {
+        if idx < stmts.len() {
+            format!("s{}", idx)
+        } else {
+            has_end = true;
+            "end".to_owned()
+        }
+    };
+
+    let mut edges = Vec::new();
+    for (idx, stmt) in stmts.iter().enumerate() {
+        let label = match *stmt {
+            Stmt::Inc(r) => format!("INC {}", r.0),
+            Stmt::Dec(r) => format!("DEC {}", r.0),
+            Stmt::DecBy(r, value) => format!("DEC {} {}", r.0, value),
+            Stmt::IsZero(r, _) => format!("IS_ZERO {}", r.0),
+            Stmt::IsNotZero(r, _) => format!("IS_NOT_ZERO {}", r.0),
+            Stmt::IsEq(r, value, _) => format!("IS_EQ {} {}", r.0, value),
+            Stmt::Swap(a, b) => format!("SWAP {} {}", a.0, b.0),
+            Stmt::Rand(r, max) => format!("RAND {} {}", r.0, max),
+            Stmt::Read(r) => format!("READ {}", r.0),
+            Stmt::Jump(_) => "JUMP".to_owned(),
+            Stmt::Call(_) => "CALL".to_owned(),
+            Stmt::Ret => "RET".to_owned(),
+            Stmt::Stop => "STOP".to_owned(),
+        };
+        writeln!(
+            out,
+            "    s{} [label=\"{}: {} (line {})\"];",
+            idx,
+            idx,
+            label,
+            span[idx].line_number(line_base)
+        )
+        .unwrap();
+
+        match *stmt {
+            Stmt::Inc(_)
+            | Stmt::Dec(_)
+            | Stmt::DecBy(_, _)
+            | Stmt::Swap(_, _)
+            | Stmt::Rand(_, _)
+            | Stmt::Read(_) => {
+                edges.push((idx, node(idx + 1), None));
+            }
+            Stmt::IsZero(_, target) => {
+                edges.push((idx, node(target.0), Some("zero")));
+                edges.push((idx, node(idx + 1), Some("not zero")));
+            }
+            Stmt::IsNotZero(_, target) => {
+                edges.push((idx, node(target.0), Some("not zero")));
+                edges.push((idx, node(idx + 1), Some("zero")));
+            }
+            Stmt::IsEq(_, _, target) => {
+                edges.push((idx, node(target.0), Some("equal")));
+                edges.push((idx, node(idx + 1), Some("not equal")));
+            }
+            Stmt::Jump(target) => edges.push((idx, node(target.0), None)),
+            Stmt::Call(target) => {
+                edges.push((idx, node(target.0), Some("call")));
+                edges.push((idx, node(idx + 1), Some("return")));
+            }
+            Stmt::Ret | Stmt::Stop => {}
+        }
+    }
+
+    if has_end {
+        out.push_str("    end [shape=ellipse];\n");
+    }
+    for (from, to, label) in edges {
+        match label {
+            Some(label) => writeln!(out, "    s{} -> {} [label=\"{}\"];", from, to, label),
+            None => writeln!(out, "    s{} -> {};", from, to),
+        }
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn control_flow_graph_has_a_node_per_statement() {
+        let code = parse::parse(
+            "INC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "test".to_owned(),
+            parse::DEFAULT_MAX_REGISTER,
+            parse::DEFAULT_LINE_BASE,
+            false,
+        )
+        .unwrap();
+        let graph = control_flow_graph(&code.stmts, &code.span, parse::DEFAULT_LINE_BASE);
+        let nodes = graph
+            .lines()
+            .filter(|line| line.contains("[label=") && !line.contains("->"))
+            .count();
+        let edges = graph.lines().filter(|line| line.contains("->")).count();
+        assert_eq!(nodes, 5);
+        // INC, both branches of IS_ZERO, DEC and JUMP, STOP has none
+        assert_eq!(edges, 5);
+    }
+}
@@ -1,13 +1,163 @@
+mod bytecode;
+mod dot;
 mod parse;
 mod run;
 
+use run::{Config, Io, OverflowPolicy, Program};
+
+/// What m8db does once all arguments are read
+enum Mode {
+    Debug,
+    Assemble {
+        input: String,
+        output: String,
+    },
+    Run(String),
+    /// Checks the programs without running them, the rest of the arguments are their paths
+    CheckOnly(Vec<String>),
+    Version,
+}
+
 fn main() {
-    println!(
-        "m8db - M8 Debugger
+    let mut args = std::env::args().skip(1);
+    let mut mode = Mode::Debug;
+    let mut program = None;
+    let mut config = Config::default();
+    let mut transcript = None;
+    let mut banner = true;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--assemble" => {
+                let input = args.next();
+                let output = match (args.next().as_deref(), args.next()) {
+                    (Some("-o"), Some(output)) => Some(output),
+                    _ => None,
+                };
+                match (input, output) {
+                    (Some(input), Some(output)) => mode = Mode::Assemble { input, output },
+                    _ => usage_error("m8db --assemble <input> -o <output>"),
+                }
+            }
+            "--load-bytecode" => match args.next() {
+                Some(path) => program = Some(Program::Bytecode(path)),
+                None => usage_error("m8db --load-bytecode <file>"),
+            },
+            "--run" => match args.next() {
+                Some(path) => mode = Mode::Run(path),
+                None => usage_error("m8db --run <file>"),
+            },
+            "--check-only" => {
+                let paths = args.by_ref().collect::<Vec<_>>();
+                if paths.is_empty() {
+                    usage_error("m8db --check-only <file>...");
+                }
+                mode = Mode::CheckOnly(paths);
+            }
+            "--version" => mode = Mode::Version,
+            "--detect-cycles" => config.detect_cycles = true,
+            "--signed" => config.signed = true,
+            "--color" => config.color = true,
+            "--time-budget" => match args.next().map(|millis| millis.parse()) {
+                Some(Ok(millis)) => {
+                    config.time_budget = Some(std::time::Duration::from_millis(millis))
+                }
+                _ => usage_error("m8db --time-budget <ms>"),
+            },
+            "--break-on-underflow" => config.break_on_underflow = true,
+            "--no-confirm" => config.no_confirm = true,
+            "--summary" => config.summary = true,
+            "--quiet" => config.quiet = true,
+            "--no-banner" => banner = false,
+            "--transcript" => match args.next() {
+                Some(path) => match std::fs::File::create(&path) {
+                    Ok(file) => transcript = Some(file),
+                    Err(why) => {
+                        eprintln!("error while creating file: {}.", why);
+                        std::process::exit(1);
+                    }
+                },
+                None => usage_error("m8db --transcript <file>"),
+            },
+            "--strict" => config.strict = true,
+            "--strict-labels" => config.strict_labels = true,
+            "--load-breakpoints" => config.load_breakpoints = true,
+            "--seed" => match args.next().map(|seed| seed.parse()) {
+                Some(Ok(seed)) => config.seed = Some(seed),
+                _ => usage_error("m8db --seed <n>"),
+            },
+            "--input-file" => match args.next() {
+                Some(path) => match run::read_inputs(&path) {
+                    Ok(inputs) => config.inputs = inputs,
+                    Err(why) => {
+                        eprintln!("{}", why);
+                        std::process::exit(1);
+                    }
+                },
+                None => usage_error("m8db --input-file <file>"),
+            },
+            "--max-register" => match args.next().map(|max| max.parse()) {
+                Some(Ok(max)) => config.max_register = Some(max),
+                _ => usage_error("m8db --max-register <index>"),
+            },
+            "--costs" => match args.next() {
+                Some(costs) => {
+                    if let Err(why) = config.costs.parse_overrides(&costs) {
+                        eprintln!("{}", why);
+                        std::process::exit(1);
+                    }
+                }
+                None => usage_error("m8db --costs <MNEMONIC=cost,...>"),
+            },
+            "--overflow" => {
+                config.overflow = match args.next().as_deref() {
+                    Some("wrap") => OverflowPolicy::Wrap,
+                    Some("saturate") => OverflowPolicy::Saturate,
+                    Some("error") => OverflowPolicy::Error,
+                    _ => usage_error("m8db --overflow <wrap|saturate|error>"),
+                }
+            }
+            _ => program = Some(Program::Source(arg)),
+        }
+    }
+
+    match mode {
+        Mode::Assemble { input, output } => {
+            if let Err(why) = run::assemble(&mut Io::stdio(), &input, &output, &config) {
+                eprintln!("{}", why);
+                std::process::exit(1);
+            }
+        }
+        Mode::Run(path) => {
+            std::process::exit(run::run_program(&mut stdio(transcript), &path, &config))
+        }
+        Mode::CheckOnly(paths) => {
+            std::process::exit(run::check_programs(&mut stdio(transcript), &paths, &config))
+        }
+        Mode::Version => println!("m8db {}", run::VERSION),
+        Mode::Debug => {
+            if banner {
+                println!(
+                    "m8db - M8 Debugger
 (C) Nilstrieb (https://github.com/Nilstrieb/m8db)
 Type 'help' for help
     "
-    );
+                );
+            }
+
+            std::process::exit(run::start(&mut stdio(transcript), program, config));
+        }
+    }
+}
+
+fn stdio(transcript: Option<std::fs::File>) -> Io {
+    match transcript {
+        Some(file) => Io::stdio().with_transcript(file),
+        None => Io::stdio(),
+    }
+}
 
-    run::start(std::env::args().nth(1));
+fn usage_error(usage: &str) -> ! {
+    eprintln!("usage: {}", usage);
+    std::process::exit(1);
 }
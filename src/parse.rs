@@ -1,28 +1,29 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Formatter;
 use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// A span referencing the line where a statement came from. Starts at 0
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Span(pub usize);
 
 impl Span {
-    pub fn line_number(&self) -> usize {
-        self.0 + 1
+    /// The number of the line as shown to the user, counting from `line_base`
+    pub fn line_number(&self, line_base: usize) -> usize {
+        self.0 + line_base
     }
 }
 
+/// The number of the first line unless changed with `linebase`. Line number jump targets in the
+/// source always count from here
+pub const DEFAULT_LINE_BASE: usize = 1;
+
 /// A line number, starts at 1
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct LineNumber(pub usize);
 
-impl LineNumber {
-    pub fn span(&self) -> Span {
-        Span(self.0 - 1)
-    }
-}
-
-/// An index into a `Vm` `Stmt`
+/// An index into a `Vm` `Stmt`, starts at 0. Jump targets are statement indices, not lines
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct StmtIdx(pub usize);
 
@@ -30,15 +31,44 @@ pub struct StmtIdx(pub usize);
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct Register(pub usize);
 
-#[derive(Debug, Copy, Clone)]
+/// The highest register index a program can use unless raised with `--max-register`. The VM
+/// allocates every register up to the highest one used, so this keeps a typo like
+/// `INC 1000000000` from allocating gigabytes
+pub const DEFAULT_MAX_REGISTER: usize = 4095;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Stmt {
     Inc(Register),
     Dec(Register),
+    /// Decrements by a constant, saturating at zero
+    DecBy(Register, usize),
     IsZero(Register, StmtIdx),
+    /// Jumps if the register is not zero
+    IsNotZero(Register, StmtIdx),
+    /// Jumps if the register has the value
+    IsEq(Register, usize, StmtIdx),
+    /// Exchanges the values of two registers
+    Swap(Register, Register),
+    /// Stores a pseudo-random value below the maximum, which is above zero
+    Rand(Register, usize),
+    /// Stores the next value of the `--input-file`
+    Read(Register),
     Jump(StmtIdx),
+    /// Pushes the index of the next statement onto the call stack and jumps
+    Call(StmtIdx),
+    /// Pops a statement index from the call stack and jumps to it
+    Ret,
     Stop,
 }
 
+/// A named, inclusive range of registers, declared with `.group name start end`
+#[derive(Debug, Clone)]
+pub struct RegisterGroup {
+    pub name: String,
+    pub start: Register,
+    pub end: Register,
+}
+
 #[derive(Debug, Clone)]
 pub struct Code<'a> {
     pub stmts: Vec<Stmt>,
@@ -46,73 +76,302 @@ pub struct Code<'a> {
     pub span: Vec<Span>,
     pub code_lines: Vec<&'a str>,
     pub file_name: String,
+    pub groups: Vec<RegisterGroup>,
+    /// Every label and the statement it refers to, in the order of the statements
+    pub labels: Vec<(String, StmtIdx)>,
+}
+
+/// The mnemonics of all statements, as written in the source
+pub const MNEMONICS: &[&str] = &[
+    "INC",
+    "DEC",
+    "IS_ZERO",
+    "IS_NOT_ZERO",
+    "IS_EQ",
+    "SWAP",
+    "RAND",
+    "READ",
+    "JUMP",
+    "CALL",
+    "RET",
+    "STOP",
+];
+
+impl Stmt {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Stmt::Inc(_) => "INC",
+            Stmt::Dec(_) | Stmt::DecBy(_, _) => "DEC",
+            Stmt::IsZero(_, _) => "IS_ZERO",
+            Stmt::IsNotZero(_, _) => "IS_NOT_ZERO",
+            Stmt::IsEq(_, _, _) => "IS_EQ",
+            Stmt::Swap(_, _) => "SWAP",
+            Stmt::Rand(_, _) => "RAND",
+            Stmt::Read(_) => "READ",
+            Stmt::Jump(_) => "JUMP",
+            Stmt::Call(_) => "CALL",
+            Stmt::Ret => "RET",
+            Stmt::Stop => "STOP",
+        }
+    }
+
+    /// The statement this one can jump to, if any
+    pub fn target(&self) -> Option<StmtIdx> {
+        match *self {
+            Stmt::IsZero(_, target)
+            | Stmt::IsNotZero(_, target)
+            | Stmt::IsEq(_, _, target)
+            | Stmt::Jump(target)
+            | Stmt::Call(target) => Some(target),
+            Stmt::Inc(_)
+            | Stmt::Dec(_)
+            | Stmt::DecBy(_, _)
+            | Stmt::Swap(_, _)
+            | Stmt::Rand(_, _)
+            | Stmt::Read(_)
+            | Stmt::Ret
+            | Stmt::Stop => None,
+        }
+    }
+
+    /// The registers this statement reads or changes, only `SWAP` has two
+    pub fn registers(&self) -> impl Iterator<Item = Register> {
+        let (first, second) = match *self {
+            Stmt::Inc(r)
+            | Stmt::Dec(r)
+            | Stmt::DecBy(r, _)
+            | Stmt::IsZero(r, _)
+            | Stmt::IsNotZero(r, _)
+            | Stmt::IsEq(r, _, _)
+            | Stmt::Rand(r, _)
+            | Stmt::Read(r) => (Some(r), None),
+            Stmt::Swap(a, b) => (Some(a), Some(b)),
+            Stmt::Jump(_) | Stmt::Call(_) | Stmt::Ret | Stmt::Stop => (None, None),
+        };
+        first.into_iter().chain(second)
+    }
 }
 
 #[derive(Debug, Clone)]
 enum IrStmt<'a> {
     Inc(Register),
     Dec(Register),
-    IsZeroLabel(Register, &'a str),
-    IsZeroLine(Register, LineNumber),
-    JumpLabel(&'a str),
-    JumpLine(LineNumber),
-    Label(&'a str),
+    DecBy(Register, usize),
+    IsZero(Register, IrTarget<'a>),
+    IsNotZero(Register, IrTarget<'a>),
+    IsEq(Register, usize, IrTarget<'a>),
+    Swap(Register, Register),
+    Rand(Register, usize),
+    Read(Register),
+    Jump(IrTarget<'a>),
+    Call(IrTarget<'a>),
+    Ret,
+    Include(&'a str),
+    Group(RegisterGroup),
     Stop,
     None,
 }
 
+/// A jump target before resolution
+#[derive(Debug, Copy, Clone)]
+enum IrTarget<'a> {
+    Label(&'a str),
+    Line(LineNumber),
+    /// Statements forward or backward from the jumping statement, `+1` is the next statement
+    Relative(isize),
+}
+
+/// A source line: a statement, optionally preceded by a label defined on the same line
+#[derive(Debug, Clone)]
+struct IrLine<'a> {
+    label: Option<&'a str>,
+    stmt: IrStmt<'a>,
+}
+
 #[derive(Debug)]
 struct ParseErr {
     span: Span,
+    /// The source line and the byte column of the offending token in it, if known
+    column: Option<(String, usize)>,
     inner: ParseErrInner,
 }
 
 impl ParseErr {
     fn new(span: Span, inner: ParseErrInner) -> Self {
-        Self { span, inner }
+        Self {
+            span,
+            column: None,
+            inner,
+        }
+    }
+}
+
+/// A line of source code that is being parsed, used to point errors at a column
+#[derive(Debug, Copy, Clone)]
+struct SourceLine<'a> {
+    span: Span,
+    text: &'a str,
+}
+
+impl<'a> SourceLine<'a> {
+    fn new(span: Span, code_lines: &[&'a str]) -> Self {
+        SourceLine {
+            span,
+            text: code_lines[span.0],
+        }
+    }
+
+    fn err(&self, inner: ParseErrInner) -> ParseErr {
+        ParseErr::new(self.span, inner)
+    }
+
+    /// `token` must be a slice of the line
+    fn err_at(&self, token: &str, inner: ParseErrInner) -> ParseErr {
+        let column = token.as_ptr() as usize - self.text.as_ptr() as usize;
+        ParseErr {
+            column: Some((self.text.to_owned(), column)),
+            ..self.err(inner)
+        }
+    }
+
+    fn err_at_end(&self, inner: ParseErrInner) -> ParseErr {
+        ParseErr {
+            column: Some((self.text.to_owned(), self.text.trim_end().len() + 1)),
+            ..self.err(inner)
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum ParseErrInner {
     OutOfBoundsLineRef(LineNumber),
+    /// A jump to a line that only has a label, a comment or nothing
+    NoStmtOnLine(LineNumber),
     LabelNotFound(String),
     ParseIntErr(ParseIntError),
     NoRegister,
     NoLabelOrLine,
+    NoValue,
+    ZeroRandMax,
+    NumericLabel(String),
+    /// A line number or relative target with `--strict-labels`
+    NumericJumpForbidden(String),
     IllegalStmt(String),
+    NoIncludePath,
+    IncludeFailed(String, String),
+    CyclicInclude(String),
+    UnresolvedInclude(String),
+    NoGroupName,
+    InvalidGroupRange(Register, Register),
+    /// The index and the maximum
+    RegisterIndexTooLarge(usize, usize),
+    TargetOutOfBounds(StmtIdx),
+    RelativeTargetOutOfBounds(isize),
 }
 
 type StdResult<T, E> = std::result::Result<T, E>;
 type Result<T> = StdResult<T, ParseErr>;
 
-impl std::fmt::Display for ParseErr {
+impl ParseErr {
+    /// The error message, with the line number counting from `line_base`
+    fn render(&self, line_base: usize) -> String {
+        let mut out = format!(
+            "error on line '{}': {}.",
+            self.span.line_number(line_base),
+            self.inner
+        );
+        if let Some((line, column)) = &self.column {
+            let prefix = line.get(..*column).unwrap_or(line);
+            let mut padding = prefix
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            padding.push_str(&" ".repeat(column.saturating_sub(prefix.len())));
+            out.push_str(&format!("\n    {}\n    {}^", line, padding));
+        }
+        out
+    }
+}
+
+impl std::fmt::Display for ParseErrInner {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "error on line '{}': ", self.span.line_number())?;
-        match &self.inner {
+        match self {
             ParseErrInner::OutOfBoundsLineRef(referenced) => {
                 write!(f, "Referencing line '{}': out of bounds", referenced.0,)
             }
+            ParseErrInner::NoStmtOnLine(referenced) => write!(
+                f,
+                "Referencing line '{}': line {} has no statement, only a label, a comment or nothing",
+                referenced.0, referenced.0
+            ),
             ParseErrInner::LabelNotFound(label) => write!(f, "Label '{}' not found", label,),
             ParseErrInner::ParseIntErr(err) => write!(f, "{}", err),
             ParseErrInner::NoRegister => write!(f, "No register provided"),
             ParseErrInner::NoLabelOrLine => write!(f, "No label or line provided"),
+            ParseErrInner::NoValue => write!(f, "No value provided"),
+            ParseErrInner::ZeroRandMax => write!(f, "The maximum of RAND must be above zero"),
+            ParseErrInner::NumericLabel(label) => write!(
+                f,
+                "Label '{}' is a number, a jump to it would be a line number or relative target",
+                label
+            ),
+            ParseErrInner::NumericJumpForbidden(target) => write!(
+                f,
+                "Jump target '{}' is not a label, which --strict-labels requires",
+                target
+            ),
             ParseErrInner::IllegalStmt(stmt) => write!(f, "Illegal statement: '{}'", stmt),
-        }?;
-        write!(f, ".")
+            ParseErrInner::NoIncludePath => write!(f, "No quoted file path provided to include"),
+            ParseErrInner::IncludeFailed(file, why) => {
+                write!(f, "Could not include '{}': {}", file, why)
+            }
+            ParseErrInner::CyclicInclude(file) => write!(f, "Cyclic include of '{}'", file),
+            ParseErrInner::UnresolvedInclude(file) => {
+                write!(f, "Include of '{}' was not resolved", file)
+            }
+            ParseErrInner::NoGroupName => write!(f, "No group name provided"),
+            ParseErrInner::InvalidGroupRange(start, end) => write!(
+                f,
+                "Group start register '{}' is after end register '{}'",
+                start.0, end.0
+            ),
+            ParseErrInner::TargetOutOfBounds(target) => {
+                write!(f, "Jump target statement '{}' does not exist", target.0)
+            }
+            ParseErrInner::RelativeTargetOutOfBounds(offset) => {
+                write!(
+                    f,
+                    "Relative jump target '{:+}' is outside of the program",
+                    offset
+                )
+            }
+            ParseErrInner::RegisterIndexTooLarge(index, max) => write!(
+                f,
+                "Register index '{}' is larger than the maximum '{}', raise it with --max-register",
+                index, max
+            ),
+        }
     }
 }
 
+/// The first statement on the line `number`, which counts from `line_base`. A line of the program
+/// without one, like a label, a comment or a blank line, is an error of its own so it isn't
+/// mistaken for a line out of bounds
 fn resolve_line_number(
-    stmts: &[(IrStmt, Span)],
+    stmt_spans: &[Span],
+    line_count: usize,
     number: LineNumber,
     span: Span,
+    line_base: usize,
 ) -> Result<StmtIdx> {
-    match stmts
+    match stmt_spans
         .iter()
-        .position(|(_, stmt_span)| stmt_span.line_number() == number.0)
+        .position(|stmt_span| stmt_span.line_number(line_base) == number.0)
     {
         Some(stmt_number) => Ok(StmtIdx(stmt_number)),
+        None if (line_base..line_base + line_count).contains(&number.0) => {
+            Err(ParseErr::new(span, ParseErrInner::NoStmtOnLine(number)))
+        }
         None => Err(ParseErr::new(
             span,
             ParseErrInner::OutOfBoundsLineRef(number),
@@ -120,18 +379,138 @@ fn resolve_line_number(
     }
 }
 
-fn resolve_label(labels: &HashMap<&str, StmtIdx>, span: Span, label: &str) -> Result<StmtIdx> {
-    match labels.get(label) {
-        Some(line) => Ok(*line),
-        None => Err(ParseErr::new(
+/// A target `offset` statements away from `stmt_idx`. Like a label after the last statement, it can
+/// point to the end of the program
+fn resolve_relative(
+    stmt_count: usize,
+    stmt_idx: usize,
+    offset: isize,
+    span: Span,
+) -> Result<StmtIdx> {
+    match stmt_idx.checked_add_signed(offset) {
+        Some(target) if target <= stmt_count => Ok(StmtIdx(target)),
+        _ => Err(ParseErr::new(
             span,
-            ParseErrInner::LabelNotFound(label.to_owned()),
+            ParseErrInner::RelativeTargetOutOfBounds(offset),
         )),
     }
 }
 
-pub fn parse(text: &str, file_name: String) -> StdResult<Code, String> {
+fn resolve_label(
+    labels: &HashMap<&str, StmtIdx>,
+    source: SourceLine<'_>,
+    label: &str,
+) -> Result<StmtIdx> {
+    match labels.get(label) {
+        Some(line) => Ok(*line),
+        None => Err(source.err_at(label, ParseErrInner::LabelNotFound(label.to_owned()))),
+    }
+}
+
+/// Turns a statement into its final form, `stmt` must be an instruction
+fn resolve_stmt<'a>(
+    stmt: &IrStmt<'a>,
+    resolve: impl Fn(IrTarget<'a>) -> Result<StmtIdx>,
+) -> Result<Stmt> {
+    Ok(match *stmt {
+        IrStmt::Inc(r) => Stmt::Inc(r),
+        IrStmt::Dec(r) => Stmt::Dec(r),
+        IrStmt::DecBy(r, value) => Stmt::DecBy(r, value),
+        IrStmt::Swap(a, b) => Stmt::Swap(a, b),
+        IrStmt::Rand(r, max) => Stmt::Rand(r, max),
+        IrStmt::Read(r) => Stmt::Read(r),
+        IrStmt::IsZero(r, target) => Stmt::IsZero(r, resolve(target)?),
+        IrStmt::IsNotZero(r, target) => Stmt::IsNotZero(r, resolve(target)?),
+        IrStmt::IsEq(r, value, target) => Stmt::IsEq(r, value, resolve(target)?),
+        IrStmt::Jump(target) => Stmt::Jump(resolve(target)?),
+        IrStmt::Call(target) => Stmt::Call(resolve(target)?),
+        IrStmt::Ret => Stmt::Ret,
+        IrStmt::Stop => Stmt::Stop,
+        IrStmt::Include(_) => unreachable!(),
+        IrStmt::Group(_) => unreachable!(),
+        IrStmt::None => unreachable!(),
+    })
+}
+
+/// Replaces every `INCLUDE "file"` line with the lines of that file, recursively.
+/// Paths are relative to the including file. Line numbers in the result are global, so every line
+/// after an include is shifted by the length of the included file.
+pub fn resolve_includes(text: &str, path: &Path, line_base: usize) -> StdResult<String, String> {
+    let mut include_stack = Vec::new();
+    let mut out = String::new();
+    include_into(text, path, line_base, &mut include_stack, &mut out)?;
+    Ok(out)
+}
+
+fn include_into(
+    text: &str,
+    path: &Path,
+    line_base: usize,
+    include_stack: &mut Vec<PathBuf>,
+    out: &mut String,
+) -> StdResult<(), String> {
+    include_stack.push(path.canonicalize().unwrap_or_else(|_| path.to_owned()));
+
+    for (line_index, line) in text.lines().enumerate() {
+        let span = Span(line_index);
+        let source = SourceLine { span, text: line };
+        match parse_line(span, line) {
+            Ok(IrLine {
+                label: Some(_),
+                stmt: IrStmt::Include(_),
+            }) => {
+                return Err(source
+                    .err(ParseErrInner::IllegalStmt(line.trim().to_owned()))
+                    .render(line_base))
+            }
+            Ok(IrLine {
+                label: None,
+                stmt: IrStmt::Include(file),
+            }) => {
+                let include_failed = |why: std::io::Error| {
+                    source
+                        .err_at(
+                            file,
+                            ParseErrInner::IncludeFailed(file.to_owned(), why.to_string()),
+                        )
+                        .render(line_base)
+                };
+                let include_path = path.parent().unwrap_or_else(|| Path::new("")).join(file);
+                let canonical = include_path.canonicalize().map_err(include_failed)?;
+                if include_stack.contains(&canonical) {
+                    return Err(source
+                        .err_at(file, ParseErrInner::CyclicInclude(file.to_owned()))
+                        .render(line_base));
+                }
+                let content = std::fs::read(&include_path)
+                    .and_then(|bytes| source_from_bytes(bytes, line_base))
+                    .map_err(include_failed)?;
+                include_into(&content, &include_path, line_base, include_stack, out)
+                    .map_err(|err| format!("in '{}': {}", file, err))?;
+            }
+            _ => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+
+    include_stack.pop();
+    Ok(())
+}
+
+/// Parses a program with includes already resolved. Errors count lines from `line_base`. With
+/// `strict_labels`, only labels are allowed as jump targets, since line numbers and relative
+/// targets break when lines are inserted
+pub fn parse(
+    text: &str,
+    file_name: String,
+    max_register: usize,
+    line_base: usize,
+    strict_labels: bool,
+) -> StdResult<Code<'_>, String> {
     let mut labels = HashMap::new();
+    let mut groups = Vec::new();
 
     let mut ir_statements = Vec::new();
     let mut statement_number = StmtIdx(0);
@@ -140,63 +519,378 @@ pub fn parse(text: &str, file_name: String) -> StdResult<Code, String> {
 
     for (line_index, line) in code_lines.iter().enumerate() {
         let span = Span(line_index);
-        let result = parse_line(span, line);
-        match result {
-            Ok(IrStmt::Label(name)) => {
+        let source = SourceLine { span, text: line };
+        for part in strip_comment(line).split(STMT_DELIMITER) {
+            let IrLine { label, stmt } =
+                parse_part(source, part).map_err(|err| err.render(line_base))?;
+            if let Some(name) = label {
                 labels.insert(name, statement_number);
             }
-            Ok(IrStmt::None) => {}
-            Ok(stmt) => {
-                statement_number.0 += 1;
-                ir_statements.push((stmt, span));
+            match stmt {
+                IrStmt::None => {}
+                IrStmt::Group(group) => groups.push(group),
+                IrStmt::Include(file) => {
+                    return Err(ParseErr::new(
+                        span,
+                        ParseErrInner::UnresolvedInclude(file.to_owned()),
+                    )
+                    .render(line_base))
+                }
+                stmt => {
+                    statement_number.0 += 1;
+                    ir_statements.push((stmt, span));
+                }
             }
-            Err(err) => return Err(err.to_string()),
         }
     }
 
+    let stmt_spans = ir_statements
+        .iter()
+        .map(|(_, span)| *span)
+        .collect::<Vec<_>>();
+    let resolve = |target: IrTarget<'_>, stmt_idx: usize, span: Span| match target {
+        IrTarget::Label(label) => resolve_label(&labels, SourceLine::new(span, &code_lines), label),
+        IrTarget::Line(LineNumber(line_number)) if strict_labels => Err(ParseErr::new(
+            span,
+            ParseErrInner::NumericJumpForbidden(line_number.to_string()),
+        )),
+        IrTarget::Relative(offset) if strict_labels => Err(ParseErr::new(
+            span,
+            ParseErrInner::NumericJumpForbidden(format!("{:+}", offset)),
+        )),
+        IrTarget::Line(line_number) => resolve_line_number(
+            &stmt_spans,
+            code_lines.len(),
+            line_number,
+            span,
+            DEFAULT_LINE_BASE,
+        ),
+        IrTarget::Relative(offset) => resolve_relative(stmt_spans.len(), stmt_idx, offset, span),
+    };
+
     let statements: Result<Vec<_>> = ir_statements
         .iter()
-        .filter(|stmt| !matches!(stmt, (IrStmt::None, _)))
-        .map(|(stmt, span)| match *stmt {
-            IrStmt::Inc(r) => Ok((Stmt::Inc(r), *span)),
-            IrStmt::Dec(r) => Ok((Stmt::Dec(r), *span)),
-            IrStmt::IsZeroLine(r, line_number) => Ok((
-                Stmt::IsZero(r, resolve_line_number(&ir_statements, line_number, *span)?),
-                *span,
-            )),
-            IrStmt::JumpLine(line_number) => Ok((
-                Stmt::Jump(resolve_line_number(&ir_statements, line_number, *span)?),
-                *span,
-            )),
-            IrStmt::IsZeroLabel(r, label) => Ok((
-                Stmt::IsZero(r, resolve_label(&labels, *span, label)?),
-                *span,
-            )),
-            IrStmt::JumpLabel(label) => {
-                Ok((Stmt::Jump(resolve_label(&labels, *span, label)?), *span))
-            }
-            IrStmt::Stop => Ok((Stmt::Stop, *span)),
-            IrStmt::Label(_) => unreachable!(),
-            IrStmt::None => unreachable!(),
+        .enumerate()
+        .map(|(stmt_idx, (stmt, span))| {
+            let stmt = resolve_stmt(stmt, |target| resolve(target, stmt_idx, *span))?;
+            Ok((stmt, *span))
         })
         .collect();
 
-    statements
-        .map(|vec| {
-            let (stmts, span) = vec.iter().cloned().unzip();
-            Code {
-                stmts,
-                span,
-                code_lines,
-                file_name,
-            }
+    let (stmts, span): (Vec<_>, Vec<_>) = statements
+        .map_err(|err| err.render(line_base))?
+        .into_iter()
+        .unzip();
+    if stmts.is_empty() {
+        return Err(NO_STATEMENTS.to_owned());
+    }
+    validate_targets(&stmts, &span, line_base)?;
+    validate_registers(&stmts, &span, max_register, line_base)?;
+    let mut labels = labels
+        .into_iter()
+        .map(|(name, stmt)| (name.to_owned(), stmt))
+        .collect::<Vec<_>>();
+    labels.sort_by(|(a_name, a_stmt), (b_name, b_stmt)| (a_stmt, a_name).cmp(&(b_stmt, b_name)));
+    Ok(Code {
+        stmts,
+        span,
+        code_lines,
+        file_name,
+        groups,
+        labels,
+    })
+}
+
+/// Decodes a source file, reporting where the first invalid UTF-8 byte is instead of the generic
+/// error of `read_to_string`. The line counts from `line_base`
+pub fn source_from_bytes(bytes: Vec<u8>, line_base: usize) -> std::io::Result<String> {
+    String::from_utf8(bytes).map_err(|err| {
+        let position = err.utf8_error().valid_up_to();
+        let line = Span(
+            err.as_bytes()[..position]
+                .iter()
+                .filter(|&&byte| byte == b'\n')
+                .count(),
+        );
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Invalid UTF-8 at byte {} on line {}",
+                position,
+                line.line_number(line_base)
+            ),
+        )
+    })
+}
+
+/// Separates several statements on one line like `INC 0 | DEC 1`. A jump to the line goes to
+/// the first one
+pub const STMT_DELIMITER: char = '|';
+
+/// The error for a program that only has blank lines, comments and labels, it would run out of
+/// bounds right away
+pub const NO_STATEMENTS: &str = "error: Program contains no executable statements.";
+
+/// Parses a number that may contain `_` separators like `1_000`, but not start with one
+pub fn parse_number<T: FromStr>(text: &str) -> StdResult<T, T::Err> {
+    if text.starts_with('_') {
+        // fails with the error of the unchanged text
+        return text.parse();
+    }
+    text.replace('_', "").parse()
+}
+
+/// Checks that every jump target is a statement or the end of the program. The end, one past the
+/// last statement, is a valid target since a label after the last statement refers to it, jumping
+/// there runs out of bounds like falling off the last statement. Resolution should never produce
+/// anything else, but bytecode can
+pub fn validate_targets(stmts: &[Stmt], span: &[Span], line_base: usize) -> StdResult<(), String> {
+    for (stmt, span) in stmts.iter().zip(span) {
+        if let Some(target) = stmt.target().filter(|target| target.0 > stmts.len()) {
+            return Err(
+                ParseErr::new(*span, ParseErrInner::TargetOutOfBounds(target)).render(line_base),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Warns about registers that `DEC` or a conditional reads but no `INC`, `SWAP`, `RAND` or `READ`
+/// ever writes, they are always 0 unless changed with `set` while debugging. Lines count from
+/// `line_base`
+pub fn uninitialized_reads(stmts: &[Stmt], span: &[Span], line_base: usize) -> Vec<String> {
+    let incremented = stmts
+        .iter()
+        .filter(|stmt| {
+            matches!(
+                stmt,
+                Stmt::Inc(_) | Stmt::Swap(_, _) | Stmt::Rand(_, _) | Stmt::Read(_)
+            )
         })
-        .map_err(|err| err.to_string())
+        .flat_map(Stmt::registers)
+        .collect::<HashSet<_>>();
+
+    let mut warned = HashSet::new();
+    let mut warnings = Vec::new();
+    for (stmt, span) in stmts.iter().zip(span) {
+        if let Stmt::Inc(_) | Stmt::Swap(_, _) | Stmt::Rand(_, _) | Stmt::Read(_) = stmt {
+            continue;
+        }
+        for r in stmt.registers() {
+            if !incremented.contains(&r) && warned.insert(r) {
+                warnings.push(format!(
+                    "warning on line '{}': Register {} is read by {} but never incremented.",
+                    span.line_number(line_base),
+                    r.0,
+                    stmt.mnemonic()
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Checks that no statement uses a register above `max_register`, for programs and bytecode
+pub fn validate_registers(
+    stmts: &[Stmt],
+    span: &[Span],
+    max_register: usize,
+    line_base: usize,
+) -> StdResult<(), String> {
+    for (stmt, span) in stmts.iter().zip(span) {
+        if let Some(r) = stmt.registers().find(|r| r.0 > max_register) {
+            return Err(ParseErr::new(
+                *span,
+                ParseErrInner::RegisterIndexTooLarge(r.0, max_register),
+            )
+            .render(line_base));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a single instruction outside of a program.
+/// Jump targets can't be resolved without a program, so only instructions that change registers are allowed
+pub fn parse_instruction(text: &str) -> StdResult<Stmt, String> {
+    let line = parse_line(Span(0), text).map_err(|err| format!("error: {}.", err.inner))?;
+    match line {
+        IrLine {
+            label: None,
+            stmt: IrStmt::Inc(r),
+        } => Ok(Stmt::Inc(r)),
+        IrLine {
+            label: None,
+            stmt: IrStmt::Dec(r),
+        } => Ok(Stmt::Dec(r)),
+        IrLine {
+            label: None,
+            stmt: IrStmt::DecBy(r, value),
+        } => Ok(Stmt::DecBy(r, value)),
+        IrLine {
+            label: None,
+            stmt: IrStmt::Swap(a, b),
+        } => Ok(Stmt::Swap(a, b)),
+        IrLine {
+            label: None,
+            stmt: IrStmt::Rand(r, max),
+        } => Ok(Stmt::Rand(r, max)),
+        IrLine {
+            label: None,
+            stmt: IrStmt::Read(r),
+        } => Ok(Stmt::Read(r)),
+        IrLine {
+            label: None,
+            stmt: IrStmt::None,
+        } => Err("error: No instruction provided.".to_owned()),
+        _ => Err("error: Only instructions that change registers can be evaluated.".to_owned()),
+    }
+}
+
+/// Parses an instruction to replace the statement `stmt_idx` on the line `span` of a loaded
+/// program. Its target is resolved against the labels and lines of that program, line numbers in
+/// it and in errors count from `line_base` like the other commands
+pub fn parse_patch(
+    text: &str,
+    span: Span,
+    stmt_idx: StmtIdx,
+    stmt_spans: &[Span],
+    line_count: usize,
+    labels: &[(String, StmtIdx)],
+    line_base: usize,
+) -> StdResult<Stmt, String> {
+    let source = SourceLine { span, text };
+    let stmt = match parse_line(span, text).map_err(|err| err.render(line_base))? {
+        IrLine {
+            label: None,
+            stmt: IrStmt::None,
+        } => return Err("error: No instruction provided.".to_owned()),
+        IrLine {
+            label: None,
+            stmt: IrStmt::Include(_) | IrStmt::Group(_),
+        }
+        | IrLine { label: Some(_), .. } => {
+            return Err("error: Only a single instruction can be patched in.".to_owned())
+        }
+        IrLine { label: None, stmt } => stmt,
+    };
+    let labels = labels
+        .iter()
+        .map(|(name, stmt)| (name.as_str(), *stmt))
+        .collect::<HashMap<_, _>>();
+    resolve_stmt(&stmt, |target| match target {
+        IrTarget::Label(label) => resolve_label(&labels, source, label),
+        IrTarget::Line(line_number) => {
+            resolve_line_number(stmt_spans, line_count, line_number, span, line_base)
+        }
+        IrTarget::Relative(offset) => resolve_relative(stmt_spans.len(), stmt_idx.0, offset, span),
+    })
+    .map_err(|err| err.render(line_base))
+}
+
+/// How a single source line was split up and what it parsed to before labels were resolved
+#[derive(Debug, Clone)]
+pub struct LineTokens<'a> {
+    pub label: Option<&'a str>,
+    /// The whitespace separated tokens after the label, without comments
+    pub tokens: Vec<&'a str>,
+    /// The debug representation of the unresolved statements, separated by `|`
+    pub ir: String,
 }
 
-fn parse_line(span: Span, line: &str) -> Result<IrStmt> {
-    let no_label_or_line_number = || ParseErr::new(span, ParseErrInner::NoLabelOrLine);
+/// Tokenizes and parses a single line like `parse` does, for inspecting the parser
+pub fn tokenize_line(
+    span: Span,
+    line: &str,
+    line_base: usize,
+) -> StdResult<LineTokens<'_>, String> {
+    let source = SourceLine { span, text: line };
+    let parts = strip_comment(line)
+        .split(STMT_DELIMITER)
+        .map(|part| parse_part(source, part))
+        .collect::<Result<Vec<_>>>()
+        .map_err(|err| err.render(line_base))?;
+    let label = parts[0].label;
+    let ir = parts
+        .iter()
+        .map(|part| format!("{:?}", part.stmt))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    let rest = strip_comment(line).trim_start();
+    let rest = match label {
+        Some(label) => rest[1 + label.len()..].trim_start(),
+        None => rest,
+    };
+    Ok(LineTokens {
+        label,
+        tokens: rest.split_whitespace().collect(),
+        ir,
+    })
+}
+
+fn parse_line(span: Span, line: &str) -> Result<IrLine<'_>> {
+    parse_part(SourceLine { span, text: line }, strip_comment(line))
+}
+
+/// Parses one statement of a line, `part` must be a slice of the line without comments
+fn parse_part<'a>(source: SourceLine<'a>, part: &'a str) -> Result<IrLine<'a>> {
+    let line = part.trim_start();
+    if let Some(labeled) = line.strip_prefix('.') {
+        let (label, rest) = labeled
+            .split_once(char::is_whitespace)
+            .unwrap_or((labeled, ""));
+        if label == "group" {
+            return Ok(IrLine {
+                label: None,
+                stmt: parse_group(source, rest)?,
+            });
+        }
+        // a jump to a number is a line or a relative target, so such a label could never be used
+        if parse_number::<isize>(label).is_ok() {
+            return Err(source.err_at(label, ParseErrInner::NumericLabel(label.to_owned())));
+        }
+        return Ok(IrLine {
+            label: Some(label),
+            stmt: parse_stmt(source, rest)?,
+        });
+    }
+
+    Ok(IrLine {
+        label: None,
+        stmt: parse_stmt(source, line)?,
+    })
+}
 
+/// Removes a comment starting with `#` or `;` at the start of a word, up to the end of the line.
+/// This also skips a `#!` shebang line, which keeps its line number
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if (c == '#' || c == ';') && previous.is_whitespace() {
+            return &line[..i];
+        }
+        previous = c;
+    }
+    line
+}
+
+fn parse_group<'a>(source: SourceLine<'a>, args: &'a str) -> Result<IrStmt<'a>> {
+    let mut iter = args.split_whitespace();
+    let name = iter
+        .next()
+        .ok_or_else(|| source.err_at_end(ParseErrInner::NoGroupName))?;
+    let start = next_register(&mut iter, source)?;
+    let end = next_register(&mut iter, source)?;
+    if start > end {
+        return Err(source.err(ParseErrInner::InvalidGroupRange(start, end)));
+    }
+    Ok(IrStmt::Group(RegisterGroup {
+        name: name.to_owned(),
+        start,
+        end,
+    }))
+}
+
+fn parse_stmt<'a>(source: SourceLine<'a>, line: &'a str) -> Result<IrStmt<'a>> {
     let mut iter = line.split_whitespace();
     let first = iter.next();
     let first = match first {
@@ -206,52 +900,435 @@ fn parse_line(span: Span, line: &str) -> Result<IrStmt> {
 
     Ok(match first {
         "INC" => {
-            let register = next_register(&mut iter, span)?;
+            let register = next_register(&mut iter, source)?;
             IrStmt::Inc(register)
         }
         "DEC" => {
-            let register = next_register(&mut iter, span)?;
-            IrStmt::Dec(register)
+            let register = next_register(&mut iter, source)?;
+            match iter.next() {
+                Some(value) => {
+                    let value = parse_number(value).map_err(|parse_err| {
+                        source.err_at(value, ParseErrInner::ParseIntErr(parse_err))
+                    })?;
+                    IrStmt::DecBy(register, value)
+                }
+                None => IrStmt::Dec(register),
+            }
         }
         "IS_ZERO" => {
-            let register = next_register(&mut iter, span)?;
-            let jump_target = iter.next().ok_or_else(no_label_or_line_number)?;
-            if let Ok(line_number) = jump_target.parse::<usize>() {
-                IrStmt::IsZeroLine(register, LineNumber(line_number))
-            } else {
-                IrStmt::IsZeroLabel(register, jump_target)
-            }
+            let register = next_register(&mut iter, source)?;
+            IrStmt::IsZero(register, next_target(&mut iter, source)?)
+        }
+        "IS_NOT_ZERO" => {
+            let register = next_register(&mut iter, source)?;
+            IrStmt::IsNotZero(register, next_target(&mut iter, source)?)
+        }
+        "IS_EQ" => {
+            let register = next_register(&mut iter, source)?;
+            let value = iter
+                .next()
+                .ok_or_else(|| source.err_at_end(ParseErrInner::NoValue))?;
+            let value = parse_number(value)
+                .map_err(|parse_err| source.err_at(value, ParseErrInner::ParseIntErr(parse_err)))?;
+            IrStmt::IsEq(register, value, next_target(&mut iter, source)?)
         }
-        "JUMP" => {
-            let jump_target = iter.next().ok_or_else(no_label_or_line_number)?;
-            if let Ok(line_number) = jump_target.parse::<usize>() {
-                IrStmt::JumpLine(LineNumber(line_number))
-            } else {
-                IrStmt::JumpLabel(jump_target)
+        "SWAP" => {
+            let a = next_register(&mut iter, source)?;
+            IrStmt::Swap(a, next_register(&mut iter, source)?)
+        }
+        "RAND" => {
+            let register = next_register(&mut iter, source)?;
+            let max = iter
+                .next()
+                .ok_or_else(|| source.err_at_end(ParseErrInner::NoValue))?;
+            match parse_number(max) {
+                Ok(0) => return Err(source.err_at(max, ParseErrInner::ZeroRandMax)),
+                Ok(value) => IrStmt::Rand(register, value),
+                Err(parse_err) => {
+                    return Err(source.err_at(max, ParseErrInner::ParseIntErr(parse_err)))
+                }
             }
         }
+        "READ" => IrStmt::Read(next_register(&mut iter, source)?),
+        "JUMP" => IrStmt::Jump(next_target(&mut iter, source)?),
+        "CALL" => IrStmt::Call(next_target(&mut iter, source)?),
+        "RET" => IrStmt::Ret,
         "STOP" => IrStmt::Stop,
-        stmt => {
-            if let Some(stripped) = stmt.strip_prefix('.') {
-                IrStmt::Label(stripped)
-            } else if stmt.starts_with('#') {
-                IrStmt::None
-            } else {
-                return Err(ParseErr::new(
-                    span,
-                    ParseErrInner::IllegalStmt(stmt.to_owned()),
-                ));
-            }
+        "INCLUDE" => {
+            let file = line
+                .trim_start()
+                .strip_prefix("INCLUDE")
+                .map(str::trim)
+                .and_then(|rest| rest.strip_prefix('"'))
+                .and_then(|rest| rest.strip_suffix('"'))
+                .filter(|file| !file.is_empty())
+                .ok_or_else(|| source.err_at(first, ParseErrInner::NoIncludePath))?;
+            IrStmt::Include(file)
         }
+        stmt => return Err(source.err_at(stmt, ParseErrInner::IllegalStmt(stmt.to_owned()))),
     })
 }
 
-fn next_register<'a>(iter: &mut impl Iterator<Item = &'a str>, span: Span) -> Result<Register> {
-    iter.next()
-        .ok_or_else(|| ParseErr::new(span, ParseErrInner::NoRegister))?
-        .parse()
-        .map(|num| Register(num))
-        .map_err(|parse_err: ParseIntError| {
-            ParseErr::new(span, ParseErrInner::ParseIntErr(parse_err))
-        })
+/// A label, a line number, or a relative target like `+2` or `-1`
+fn next_target<'a>(
+    iter: &mut impl Iterator<Item = &'a str>,
+    source: SourceLine<'a>,
+) -> Result<IrTarget<'a>> {
+    let token = iter
+        .next()
+        .ok_or_else(|| source.err_at_end(ParseErrInner::NoLabelOrLine))?;
+    if token.starts_with(['+', '-']) {
+        return parse_number(token)
+            .map(IrTarget::Relative)
+            .map_err(|parse_err| source.err_at(token, ParseErrInner::ParseIntErr(parse_err)));
+    }
+    Ok(match parse_number(token) {
+        Ok(line_number) => IrTarget::Line(LineNumber(line_number)),
+        Err(_) => IrTarget::Label(token),
+    })
+}
+
+fn next_register<'a>(
+    iter: &mut impl Iterator<Item = &'a str>,
+    source: SourceLine<'_>,
+) -> Result<Register> {
+    let token = iter
+        .next()
+        .ok_or_else(|| source.err_at_end(ParseErrInner::NoRegister))?;
+    let index = parse_number(token).map_err(|parse_err: ParseIntError| {
+        source.err_at(token, ParseErrInner::ParseIntErr(parse_err))
+    })?;
+    Ok(Register(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn parse_ok(text: &str) -> Code<'_> {
+        parse(
+            text,
+            "test".to_owned(),
+            DEFAULT_MAX_REGISTER,
+            DEFAULT_LINE_BASE,
+            false,
+        )
+        .unwrap()
+    }
+
+    /// A temp file name that no other test uses, and its full path
+    fn temp_file() -> (String, PathBuf) {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "m8db-parse-{}-{}.m8",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(&name);
+        (name, path)
+    }
+
+    #[test]
+    fn dec_with_and_without_value() {
+        let code = parse_ok("DEC 3\nDEC 3 5\n");
+        assert_eq!(
+            code.stmts,
+            [Stmt::Dec(Register(3)), Stmt::DecBy(Register(3), 5)]
+        );
+    }
+
+    #[test]
+    fn label_on_the_line_of_an_instruction() {
+        let code = parse_ok("INC 1\nJUMP target\nINC 2\n.target INC 0\nSTOP\n");
+        assert_eq!(code.stmts[1], Stmt::Jump(StmtIdx(3)));
+        assert_eq!(code.stmts[3], Stmt::Inc(Register(0)));
+    }
+
+    #[test]
+    fn jump_to_the_first_line() {
+        let code = parse_ok("INC 0\nJUMP 1\n");
+        assert_eq!(code.stmts[1], Stmt::Jump(StmtIdx(0)));
+    }
+
+    #[test]
+    fn error_points_at_the_column_of_the_token() {
+        let error = parse_line(Span(1), "IS_ZERO  x 5").unwrap_err();
+        assert!(matches!(error.inner, ParseErrInner::ParseIntErr(_)));
+        assert_eq!(error.column, Some(("IS_ZERO  x 5".to_owned(), 9)));
+
+        let error = parse(
+            "INC 0\nINC  x\n",
+            "test".to_owned(),
+            DEFAULT_MAX_REGISTER,
+            DEFAULT_LINE_BASE,
+            false,
+        )
+        .unwrap_err();
+        assert!(error.ends_with("\n    INC  x\n         ^"), "{}", error);
+    }
+
+    #[test]
+    fn semicolon_starts_a_comment() {
+        assert!(matches!(
+            parse_line(Span(0), "; a comment").unwrap().stmt,
+            IrStmt::None
+        ));
+        assert!(matches!(
+            parse_line(Span(0), "INC 4 ; note").unwrap().stmt,
+            IrStmt::Inc(Register(4))
+        ));
+        assert!(matches!(
+            parse_line(Span(0), "INC 4 # note").unwrap().stmt,
+            IrStmt::Inc(Register(4))
+        ));
+    }
+
+    #[test]
+    fn included_program_is_inlined() {
+        let (name, included) = temp_file();
+        std::fs::write(&included, "INC 1\nINC 1\n").unwrap();
+        let (_, main) = temp_file();
+        let source = format!("INC 0\nINCLUDE \"{}\"\nSTOP\n", name);
+
+        let resolved = resolve_includes(&source, &main, DEFAULT_LINE_BASE).unwrap();
+        let code = parse_ok(&resolved);
+        assert_eq!(
+            code.stmts,
+            [
+                Stmt::Inc(Register(0)),
+                Stmt::Inc(Register(1)),
+                Stmt::Inc(Register(1)),
+                Stmt::Stop
+            ]
+        );
+        std::fs::remove_file(included).unwrap();
+    }
+
+    #[test]
+    fn cyclic_include_is_an_error() {
+        let (a_name, a) = temp_file();
+        let (b_name, b) = temp_file();
+        std::fs::write(&a, format!("INC 0\nINCLUDE \"{}\"\n", b_name)).unwrap();
+        std::fs::write(&b, format!("INC 1\nINCLUDE \"{}\"\n", a_name)).unwrap();
+
+        let error = resolve_includes(&std::fs::read_to_string(&a).unwrap(), &a, DEFAULT_LINE_BASE)
+            .unwrap_err();
+        assert!(error.contains("Cyclic include"), "{}", error);
+        std::fs::remove_file(a).unwrap();
+        std::fs::remove_file(b).unwrap();
+    }
+
+    #[test]
+    fn adversarial_input_never_panics() {
+        let long_token = "A".repeat(100_000);
+        let long_number = "9".repeat(1_000);
+        let inputs = [
+            String::new(),
+            "   \n\t\n  \t  \n".to_owned(),
+            ".".to_owned(),
+            ". INC 0".to_owned(),
+            "INC".to_owned(),
+            "INC -1".to_owned(),
+            "INC 18446744073709551616".to_owned(),
+            format!("INC {}", long_number),
+            format!("DEC 0 {}", long_number),
+            format!("JUMP {}", long_number),
+            "JUMP 0".to_owned(),
+            long_token.clone(),
+            format!(".{} INC 0", long_token),
+            format!("JUMP {}", long_token),
+            "INCLUDE".to_owned(),
+            "INCLUDE \"".to_owned(),
+            "INCLUDE \"\"".to_owned(),
+            "INCLUDE \"missing.m8\"".to_owned(),
+            ".label INCLUDE \"missing.m8\"".to_owned(),
+            "IS_ZERO 0".to_owned(),
+            "\u{0}\u{feff}\u{202e}".to_owned(),
+        ];
+        for input in &inputs {
+            let _ = parse(
+                input,
+                "test".to_owned(),
+                DEFAULT_MAX_REGISTER,
+                DEFAULT_LINE_BASE,
+                false,
+            );
+            let _ = resolve_includes(input, Path::new("test.m8"), DEFAULT_LINE_BASE);
+            let _ = parse_instruction(input);
+        }
+    }
+
+    #[test]
+    fn register_index_above_the_maximum_is_an_error() {
+        assert!(parse(
+            "INC 4095\n",
+            "test".to_owned(),
+            DEFAULT_MAX_REGISTER,
+            DEFAULT_LINE_BASE,
+            false,
+        )
+        .is_ok());
+        let error = parse(
+            "INC 4096\n",
+            "test".to_owned(),
+            DEFAULT_MAX_REGISTER,
+            DEFAULT_LINE_BASE,
+            false,
+        )
+        .unwrap_err();
+        assert!(error.contains("4096"), "{}", error);
+    }
+
+    #[test]
+    fn target_past_the_end_is_rejected() {
+        let span = [Span(0), Span(1)];
+        let out_of_bounds = [Stmt::Inc(Register(0)), Stmt::Jump(StmtIdx(3))];
+        let error = validate_targets(&out_of_bounds, &span, DEFAULT_LINE_BASE).unwrap_err();
+        assert!(error.starts_with("error on line '2'"), "{}", error);
+    }
+
+    #[test]
+    fn jump_to_the_end_of_the_program_is_valid() {
+        // a label after the last statement refers to the end
+        let code = parse_ok("INC 0\nJUMP end\n.end\n");
+        assert_eq!(code.stmts[1], Stmt::Jump(StmtIdx(2)));
+        assert!(validate_targets(&code.stmts, &code.span, DEFAULT_LINE_BASE).is_ok());
+    }
+
+    #[test]
+    fn relative_jumps_forward_and_backward() {
+        let code = parse_ok("INC 0\nJUMP +2\nINC 1\nINC 2\nIS_ZERO 3 -3\n");
+        assert!(matches!(code.stmts[1], Stmt::Jump(StmtIdx(3))));
+        assert!(matches!(
+            code.stmts[4],
+            Stmt::IsZero(Register(3), StmtIdx(1))
+        ));
+        assert!(parse(
+            "JUMP -2\n",
+            "test".to_owned(),
+            DEFAULT_MAX_REGISTER,
+            DEFAULT_LINE_BASE,
+            false,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reading_a_register_that_is_never_written_warns() {
+        let code = parse_ok("DEC 1\nSTOP\n");
+        let warnings = uninitialized_reads(&code.stmts, &code.span, DEFAULT_LINE_BASE);
+        assert_eq!(
+            warnings,
+            vec![
+                "warning on line '1': Register 1 is read by DEC but never incremented.".to_owned()
+            ]
+        );
+        let code = parse_ok("INC 1\nDEC 1\nSTOP\n");
+        assert!(uninitialized_reads(&code.stmts, &code.span, DEFAULT_LINE_BASE).is_empty());
+    }
+
+    #[test]
+    fn huge_register_index_is_rejected() {
+        let error = parse(
+            "INC 0\nINC 1000000000\n",
+            "test".to_owned(),
+            4095,
+            DEFAULT_LINE_BASE,
+            false,
+        )
+        .unwrap_err();
+        assert!(
+            error.contains("Register index '1000000000' is larger than the maximum '4095'"),
+            "{}",
+            error
+        );
+        assert!(parse(
+            "INC 4095\n",
+            "test".to_owned(),
+            4095,
+            DEFAULT_LINE_BASE,
+            false
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn delimited_line_has_several_statements() {
+        let code = parse_ok("INC 0 | INC 1 | DEC 0\nIS_ZERO 2 1\nINC 2 | INC 3 ; DEC 3 | DEC 2\n");
+        assert!(matches!(
+            code.stmts[..],
+            [
+                Stmt::Inc(Register(0)),
+                Stmt::Inc(Register(1)),
+                Stmt::Dec(Register(0)),
+                Stmt::IsZero(Register(2), StmtIdx(0)),
+                Stmt::Inc(Register(2)),
+                Stmt::Inc(Register(3)),
+            ]
+        ));
+        assert_eq!(code.span[..3], [Span(0), Span(0), Span(0)]);
+    }
+
+    #[test]
+    fn invalid_utf8_reports_the_byte_and_line() {
+        let error =
+            source_from_bytes(b"INC 0\nINC \xff\n".to_vec(), DEFAULT_LINE_BASE).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Invalid UTF-8 at byte 10 on line 2");
+        assert_eq!(
+            source_from_bytes(b"INC 0\n".to_vec(), DEFAULT_LINE_BASE).unwrap(),
+            "INC 0\n"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_line_follows_the_line_base() {
+        let error = source_from_bytes(b"INC 0\nINC \xff\n".to_vec(), 0).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid UTF-8 at byte 10 on line 1");
+    }
+
+    #[test]
+    fn numeric_label_is_rejected_and_number_is_a_line() {
+        let error = parse_line(Span(0), ".5 INC 0").unwrap_err();
+        assert!(matches!(error.inner, ParseErrInner::NumericLabel(label) if label == "5"));
+        assert!(matches!(
+            parse_line(Span(0), "JUMP 5").unwrap().stmt,
+            IrStmt::Jump(IrTarget::Line(LineNumber(5)))
+        ));
+    }
+
+    #[test]
+    fn strict_labels_reject_numeric_jumps() {
+        let strict = |text| {
+            parse(
+                text,
+                "test".to_owned(),
+                DEFAULT_MAX_REGISTER,
+                DEFAULT_LINE_BASE,
+                true,
+            )
+        };
+        let error = strict("INC 0\nJUMP 1\n").unwrap_err();
+        assert!(error.starts_with("error on line '2'"), "{}", error);
+        assert!(strict("INC 0\nJUMP +1\n").is_err());
+        assert!(strict(".start INC 0\nJUMP start\n").is_ok());
+    }
+
+    #[test]
+    fn jump_to_a_label_only_line_has_no_statement() {
+        let error = parse(
+            "INC 0\n.end\nJUMP 2\n",
+            "test".to_owned(),
+            DEFAULT_MAX_REGISTER,
+            DEFAULT_LINE_BASE,
+            false,
+        )
+        .unwrap_err();
+        assert!(
+            error.contains("Referencing line '2': line 2 has no statement"),
+            "{}",
+            error
+        );
+    }
 }
@@ -1,17 +1,135 @@
+use crate::bytecode;
+use crate::dot;
 use crate::parse;
-use crate::parse::{Code, LineNumber, Register, Span, Stmt, StmtIdx};
-use std::io::Write;
-use std::path::Path;
+use crate::parse::{Code, Register, RegisterGroup, Span, Stmt, StmtIdx};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone)]
-struct Vm<'a> {
+struct Vm {
     stmts: Vec<Stmt>,
     span: Vec<Span>,
-    code_lines: Vec<&'a str>,
+    code_lines: Vec<String>,
     pc: StmtIdx,
     registers: Vec<usize>,
+    /// The statements to return to with `RET`, the innermost call last
+    call_stack: Vec<StmtIdx>,
     breakpoints: Vec<StmtIdx>,
+    change_breakpoints: Vec<ChangeBreakpoint>,
+    count_breakpoints: Vec<CountBreakpoint>,
+    /// How often the pc reached each breakpoint since it was set
+    breakpoint_hits: HashMap<StmtIdx, u64>,
+    /// How often a breakpoint of any kind fired in this session, also counting deleted ones
+    breaks: u64,
+    /// The registers when the last breakpoint fired, the initial registers before that
+    break_registers: Vec<usize>,
+    /// The registers when the breakpoint before the last one fired, for `r diff-since-break`
+    previous_break_registers: Vec<usize>,
     file_name: String,
+    /// The highest register index `set` and `eval` can use
+    max_register: usize,
+    /// The source file, `None` for stdin and bytecode. Breakpoints are saved next to it
+    source_path: Option<PathBuf>,
+    groups: Vec<RegisterGroup>,
+    /// Every label and the statement it refers to, empty for bytecode
+    labels: Vec<(String, StmtIdx)>,
+    /// Every state seen during the current run, if cycle detection is enabled
+    seen_states: Option<HashSet<VmSnapshot>>,
+    /// Print the changed registers after every `step`
+    step_diff: bool,
+    /// Print the program view after every `step`, set with `stepshow`
+    step_show: bool,
+    /// Repaint the registers on one line while `continue` runs
+    live_registers: bool,
+    /// How register values are shown, set with `format`
+    register_format: RegisterFormat,
+    /// The number of the first line in commands and output, 1 unless changed with `linebase`
+    line_base: usize,
+    /// Registers hold two's complement `isize` values, so `DEC` can go below zero
+    signed: bool,
+    /// Break when a register reaches a value
+    value_watches: Vec<(Register, usize)>,
+    overflow: OverflowPolicy,
+    /// Highlight source lines with ANSI colors
+    color: bool,
+    /// Pause before a `DEC` that would take an unsigned register below zero
+    break_on_underflow: bool,
+    /// How often each statement was executed in this session
+    exec_counts: Vec<u64>,
+    /// Registers or breakpoints were changed by a command, so quitting asks for confirmation
+    dirty: bool,
+    costs: Costs,
+    /// How long a single run may take, `None` is unlimited
+    time_budget: Option<Duration>,
+    /// The total cost of all executed statements, see `Costs`
+    cycles: u64,
+    /// The last `HISTORY_LENGTH` executed statements, the most recent last
+    history: VecDeque<StmtIdx>,
+    /// Every statement executed by a `continue trace` while it runs
+    trace: Option<Vec<StmtIdx>>,
+    /// How to undo the last `HISTORY_LENGTH` steps with `back`, the most recent last
+    journal: VecDeque<JournalEntry>,
+    /// Snapshots of the whole VM saved with `fork`, the most recent last
+    forks: Vec<Vm>,
+    /// The seed `RAND` started with, shown by `stats` to reproduce a run with `--seed`
+    seed: u64,
+    random_state: u64,
+    /// The values of the `--input-file`, `READ` takes them in order
+    inputs: Vec<usize>,
+    /// The index of the next input `READ` takes
+    input_position: usize,
+}
+
+/// The pc, registers, call stack, state of `RAND` and position in the inputs of `READ`, which
+/// together decide how the program continues
+type VmSnapshot = (StmtIdx, Vec<usize>, Vec<StmtIdx>, u64, usize);
+
+/// Breaks at a statement only if a register changed since the last time the statement was reached
+#[derive(Debug, Copy, Clone)]
+struct ChangeBreakpoint {
+    stmt: StmtIdx,
+    register: Register,
+    /// The value of the register the last time the statement was reached
+    last_value: Option<usize>,
+}
+
+/// What a single step changed, to undo it with `back`
+#[derive(Debug, Clone)]
+struct JournalEntry {
+    pc: StmtIdx,
+    /// The registers the statement used and their values before, only `SWAP` uses two
+    registers: [Option<(Register, usize)>; 2],
+    call_stack: CallStackChange,
+    /// The state of `RAND` before the step
+    random_state: u64,
+    /// The statement the step went to, where the breakpoints were visited
+    next_pc: StmtIdx,
+    /// The `last_value` of the change breakpoints at `next_pc` before the step
+    change_values: Vec<(Register, Option<usize>)>,
+    /// Whether the step counted a hit for a breakpoint at `next_pc`
+    hit_breakpoint: bool,
+    /// Whether a breakpoint of any kind fired at `next_pc`, counted in `breaks`
+    broke: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum CallStackChange {
+    None,
+    /// A `CALL` pushed a return address
+    Pushed,
+    /// A `RET` popped this return address
+    Popped(StmtIdx),
+}
+
+/// Breaks at a statement only on the `count`th time it is reached
+#[derive(Debug, Copy, Clone)]
+struct CountBreakpoint {
+    stmt: StmtIdx,
+    count: u64,
+    /// How often the statement was reached since the breakpoint was set
+    hits: u64,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -20,48 +138,780 @@ enum VmState {
     Break,
     Stop,
     OutOfBounds,
+    /// The VM reached a state it has been in before, so it can never terminate
+    InfiniteLoop(StmtIdx),
+    /// The instruction budget of a `continue <n>` ran out
+    StepLimit,
+    /// The run took longer than `--time-budget`
+    TimeLimit,
+    /// A register reached the value of a value watchpoint
+    Watch(Register, usize),
+    /// A register overflowed under `OverflowPolicy::Error`, the pc stays on the instruction
+    Overflow(Register),
+    /// A `DEC` would take the register below zero with `--break-on-underflow`, nothing was changed
+    Underflow(Register),
+    /// A `RET` was reached without a `CALL`, the pc stays on the `RET`
+    EmptyCallStack,
+    /// A `READ` was reached after all inputs were read, the pc stays on the `READ`
+    NoInput,
+    /// The condition of the `RunUntil` given to `run` was met
+    Reached,
+}
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Options for the debugger, set from the command line
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub detect_cycles: bool,
+    pub signed: bool,
+    pub overflow: OverflowPolicy,
+    pub color: bool,
+    pub break_on_underflow: bool,
+    /// Quit without asking, even if the session was changed
+    pub no_confirm: bool,
+    /// Print the executed instructions, breakpoint hits and final line on `quit`
+    pub summary: bool,
+    /// Don't print informational messages, errors are still printed
+    pub quiet: bool,
+    /// An unknown command exits m8db with an error instead of being skipped
+    pub strict: bool,
+    /// Restore the breakpoints saved with `break save` when a program is loaded
+    pub load_breakpoints: bool,
+    /// The highest register index programs can use, `parse::DEFAULT_MAX_REGISTER` if not set
+    pub max_register: Option<usize>,
+    /// Jump targets must be labels, line numbers and relative targets are parse errors
+    pub strict_labels: bool,
+    /// The seed for `RAND`, based on the time if not set
+    pub seed: Option<u64>,
+    /// The values `READ` takes in order, from `--input-file`
+    pub inputs: Vec<usize>,
+    pub costs: Costs,
+    pub time_budget: Option<Duration>,
+}
+
+impl Config {
+    fn max_register(&self) -> usize {
+        self.max_register.unwrap_or(parse::DEFAULT_MAX_REGISTER)
+    }
+}
+
+const TIME_CHECK_INTERVAL: u64 = 4096;
+/// How many executed statements `history` and `back` remember
+const HISTORY_LENGTH: usize = 1000;
+/// How many lines `examine` shows if no count is given
+const EXAMINE_LINES: usize = 10;
+/// How many columns a tab in the indentation of a source line takes when printed
+const TAB_WIDTH: usize = 4;
+/// How many line numbers `continue trace` prints per line
+const TRACE_LINE_LENGTH: usize = 20;
+/// How often the registers are repainted with `live on`
+const LIVE_PAINT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many cycles each instruction takes, for the cycle counter. `DEC r value` costs the same as `DEC`
+#[derive(Debug, Clone)]
+pub struct Costs {
+    pub inc: u64,
+    pub dec: u64,
+    pub is_zero: u64,
+    pub is_not_zero: u64,
+    pub is_eq: u64,
+    pub swap: u64,
+    pub rand: u64,
+    pub read: u64,
+    pub jump: u64,
+    pub call: u64,
+    pub ret: u64,
+    pub stop: u64,
+}
+
+impl Default for Costs {
+    fn default() -> Self {
+        Costs {
+            inc: 1,
+            dec: 1,
+            is_zero: 2,
+            is_not_zero: 2,
+            is_eq: 2,
+            swap: 1,
+            rand: 1,
+            read: 1,
+            jump: 2,
+            call: 2,
+            ret: 2,
+            stop: 1,
+        }
+    }
+}
+
+impl Costs {
+    fn of(&self, stmt: Stmt) -> u64 {
+        match stmt {
+            Stmt::Inc(_) => self.inc,
+            Stmt::Dec(_) | Stmt::DecBy(_, _) => self.dec,
+            Stmt::IsZero(_, _) => self.is_zero,
+            Stmt::IsNotZero(_, _) => self.is_not_zero,
+            Stmt::IsEq(_, _, _) => self.is_eq,
+            Stmt::Swap(_, _) => self.swap,
+            Stmt::Rand(_, _) => self.rand,
+            Stmt::Read(_) => self.read,
+            Stmt::Jump(_) => self.jump,
+            Stmt::Call(_) => self.call,
+            Stmt::Ret => self.ret,
+            Stmt::Stop => self.stop,
+        }
+    }
+
+    /// Overrides costs from a list like `INC=1,IS_ZERO=3`
+    pub fn parse_overrides(&mut self, text: &str) -> Result<(), String> {
+        for pair in text.split(',') {
+            let (mnemonic, cost) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("error: Expected 'MNEMONIC=cost', got '{}'.", pair))?;
+            let cost = cost
+                .parse()
+                .map_err(|_| format!("error: Invalid cost '{}'.", cost))?;
+            match mnemonic {
+                "INC" => self.inc = cost,
+                "DEC" => self.dec = cost,
+                "IS_ZERO" => self.is_zero = cost,
+                "IS_NOT_ZERO" => self.is_not_zero = cost,
+                "IS_EQ" => self.is_eq = cost,
+                "SWAP" => self.swap = cost,
+                "RAND" => self.rand = cost,
+                "READ" => self.read = cost,
+                "JUMP" => self.jump = cost,
+                "CALL" => self.call = cost,
+                "RET" => self.ret = cost,
+                "STOP" => self.stop = cost,
+                _ => return Err(format!("error: Unknown mnemonic: {}.", mnemonic)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the debugger reads its commands from and writes its output and errors to
+pub struct Io {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    /// Where errors are written, the output if `None`
+    errors: Option<Box<dyn Write>>,
+    transcript: Option<Transcript>,
+}
+
+/// The error stream of an `Io`, for `writeln!(io.errors(), ...)`
+struct Errors<'a>(&'a mut Io);
+
+impl Errors<'_> {
+    fn write_fmt(&mut self, args: std::fmt::Arguments<'_>) {
+        let io = &mut *self.0;
+        match &mut io.errors {
+            Some(errors) => {
+                // keep the output and the errors in order if both go to a terminal
+                io.output.flush().unwrap();
+                let text = std::fmt::format(args);
+                errors.write_all(text.as_bytes()).unwrap();
+                if let Some(transcript) = &mut io.transcript {
+                    transcript.output(&text);
+                }
+            }
+            None => io.write_fmt(args),
+        }
+    }
+}
+
+/// A copy of the session for `--transcript`. Commands are written as they are and the output as
+/// `#` comments, so the transcript can be replayed with `source`. Prompts are left out
+struct Transcript {
+    output: Box<dyn Write>,
+    /// The output since the last newline
+    line: String,
+}
+
+impl Transcript {
+    fn output(&mut self, text: &str) {
+        for c in text.chars() {
+            if c == '\n' {
+                let line = std::mem::take(&mut self.line);
+                writeln!(self.output, "# {}", line).unwrap();
+            } else {
+                self.line.push(c);
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str) {
+        // the rest of the line is the prompt
+        self.line.clear();
+        writeln!(self.output, "{}", command).unwrap();
+    }
+}
+
+impl Io {
+    pub fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Io {
+            input: Box::new(input),
+            output: Box::new(output),
+            errors: None,
+            transcript: None,
+        }
+    }
+
+    /// Writes errors to `errors` instead of the output
+    pub fn with_errors(mut self, errors: impl Write + 'static) -> Self {
+        self.errors = Some(Box::new(errors));
+        self
+    }
+
+    /// Also writes the session to `output`, see `Transcript`
+    pub fn with_transcript(mut self, output: impl Write + 'static) -> Self {
+        self.transcript = Some(Transcript {
+            output: Box::new(output),
+            line: String::new(),
+        });
+        self
+    }
+
+    pub fn stdio() -> Self {
+        // not locked, a program can be read from stdin with `-`
+        Io::new(std::io::BufReader::new(std::io::stdin()), std::io::stdout())
+            .with_errors(std::io::stderr())
+    }
+
+    fn errors(&mut self) -> Errors<'_> {
+        Errors(self)
+    }
+
+    /// Reads a trimmed line, `None` at the end of the input
+    fn read_line(&mut self) -> Option<String> {
+        self.flush();
+        let mut input_buf = String::new();
+        let line = match self.input.read_line(&mut input_buf).unwrap() {
+            0 => return None,
+            _ => input_buf.trim().to_owned(),
+        };
+        if let Some(transcript) = &mut self.transcript {
+            transcript.command(&line);
+        }
+        Some(line)
+    }
+
+    fn flush(&mut self) {
+        self.output.flush().unwrap();
+        if let Some(transcript) = &mut self.transcript {
+            transcript.output.flush().unwrap();
+        }
+    }
+
+    /// Makes `write!` and `writeln!` work on `Io`. Like `print!`, this panics if writing fails
+    fn write_fmt(&mut self, args: std::fmt::Arguments<'_>) {
+        match &mut self.transcript {
+            Some(transcript) => {
+                let text = std::fmt::format(args);
+                self.output.write_all(text.as_bytes()).unwrap();
+                transcript.output(&text);
+            }
+            None => self.output.write_fmt(args).unwrap(),
+        }
+    }
+}
+
+/// What happens when an instruction increases a register past its maximum
+#[derive(Debug, Copy, Clone, Default)]
+pub enum OverflowPolicy {
+    Wrap,
+    #[default]
+    Saturate,
+    Error,
 }
 
-impl Vm<'_> {
+impl Vm {
+    fn new(code: Code, config: &Config) -> Self {
+        let max_register_index = max_register(&code.stmts);
+        let stmt_count = code.stmts.len();
+        let seed = config.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64)
+        });
+        Vm {
+            stmts: code.stmts,
+            span: code.span,
+            code_lines: code.code_lines.into_iter().map(str::to_owned).collect(),
+            file_name: code.file_name,
+            max_register: config.max_register(),
+            source_path: None,
+            groups: code.groups,
+            labels: code.labels,
+            pc: StmtIdx(0),
+            registers: vec![0; max_register_index + 1],
+            call_stack: Vec::new(),
+            breakpoints: vec![],
+            change_breakpoints: Vec::new(),
+            count_breakpoints: Vec::new(),
+            breakpoint_hits: HashMap::new(),
+            breaks: 0,
+            break_registers: vec![0; max_register_index + 1],
+            previous_break_registers: vec![0; max_register_index + 1],
+            seen_states: if config.detect_cycles {
+                Some(HashSet::new())
+            } else {
+                None
+            },
+            step_diff: false,
+            step_show: false,
+            live_registers: false,
+            register_format: RegisterFormat::Decimal,
+            line_base: parse::DEFAULT_LINE_BASE,
+            signed: config.signed,
+            value_watches: Vec::new(),
+            overflow: config.overflow,
+            color: config.color,
+            break_on_underflow: config.break_on_underflow,
+            exec_counts: vec![0; stmt_count],
+            dirty: false,
+            costs: config.costs.clone(),
+            time_budget: config.time_budget,
+            cycles: 0,
+            history: VecDeque::new(),
+            trace: None,
+            journal: VecDeque::new(),
+            forks: Vec::new(),
+            seed,
+            random_state: seed,
+            inputs: config.inputs.clone(),
+            input_position: 0,
+        }
+    }
+
+    /// The number of a line as shown to the user, see `line_base`
+    fn line(&self, span: Span) -> usize {
+        span.line_number(self.line_base)
+    }
+
+    /// The line for a line number from the user, `None` if it is below the line base
+    fn span_of(&self, line_number: usize) -> Option<Span> {
+        line_number.checked_sub(self.line_base).map(Span)
+    }
+
     fn step(&mut self) -> VmState {
         let pc = self.pc;
+        let mut next_pc = StmtIdx(pc.0 + 1);
+        let mut call_stack = CallStackChange::None;
+        let mut registers = [None; 2];
+        let random_state = self.random_state;
+        if let Some(stmt) = self.stmts.get(pc.0) {
+            for (slot, r) in registers.iter_mut().zip(stmt.registers()) {
+                *slot = Some((r, self.registers[r.0]));
+            }
+        }
+        let watched_before = self
+            .value_watches
+            .iter()
+            .map(|(r, _)| self.registers[r.0])
+            .collect::<Vec<_>>();
         match self.stmts.get(pc.0).cloned() {
-            Some(Stmt::Inc(r)) => self.registers[r.0] += 1,
-            Some(Stmt::Dec(r)) => self.registers[r.0] -= 1,
             Some(Stmt::IsZero(r, index)) => {
                 if self.registers[r.0] == 0 {
-                    self.pc = StmtIdx(index.0 - 1);
+                    next_pc = index;
+                }
+            }
+            Some(Stmt::IsNotZero(r, index)) => {
+                if self.registers[r.0] != 0 {
+                    next_pc = index;
+                }
+            }
+            Some(Stmt::IsEq(r, value, index)) => {
+                if self.registers[r.0] == value {
+                    next_pc = index;
+                }
+            }
+            Some(Stmt::Jump(index)) => next_pc = index,
+            Some(Stmt::Call(index)) => {
+                self.call_stack.push(next_pc);
+                call_stack = CallStackChange::Pushed;
+                next_pc = index;
+            }
+            Some(Stmt::Ret) => match self.call_stack.pop() {
+                Some(index) => {
+                    call_stack = CallStackChange::Popped(index);
+                    next_pc = index;
+                }
+                None => return VmState::EmptyCallStack,
+            },
+            Some(Stmt::Stop) => {
+                self.record_execution(pc);
+                return VmState::Stop;
+            }
+            Some(stmt) => {
+                if let Some(r) = self.underflowing_register(stmt) {
+                    return VmState::Underflow(r);
+                }
+                if let Err(state) = self.apply(stmt) {
+                    return state;
                 }
             }
-            Some(Stmt::Jump(index)) => self.pc = StmtIdx(index.0 - 1),
-            Some(Stmt::Stop) => return VmState::Stop,
             None => return VmState::OutOfBounds,
         }
-        self.pc.0 += 1;
-        if self.breakpoints.contains(&self.pc) {
+        self.record_execution(pc);
+        self.pc = next_pc;
+        let change_values = self
+            .change_breakpoints
+            .iter()
+            .filter(|breakpoint| breakpoint.stmt == next_pc)
+            .map(|breakpoint| (breakpoint.register, breakpoint.last_value))
+            .collect();
+        let changed = self.visit_change_breakpoints();
+        let counted = self.visit_count_breakpoints();
+        let at_breakpoint = self.breakpoints.contains(&self.pc);
+        if at_breakpoint {
+            *self.breakpoint_hits.entry(self.pc).or_default() += 1;
+        }
+        let broke = changed || counted || at_breakpoint;
+        if broke {
+            self.breaks += 1;
+        }
+        if self.journal.len() == HISTORY_LENGTH {
+            self.journal.pop_front();
+        }
+        self.journal.push_back(JournalEntry {
+            pc,
+            registers,
+            call_stack,
+            random_state,
+            next_pc,
+            change_values,
+            hit_breakpoint: at_breakpoint,
+            broke,
+        });
+
+        let reached = self
+            .value_watches
+            .iter()
+            .zip(watched_before)
+            .find(|((r, value), before)| *before != *value && self.registers[r.0] == *value);
+        if let Some((&(r, value), _)) = reached {
+            return VmState::Watch(r, value);
+        }
+        if broke {
+            self.previous_break_registers =
+                std::mem::replace(&mut self.break_registers, self.registers.clone());
             VmState::Break
         } else {
             VmState::Run
         }
     }
 
-    fn run(&mut self, time_kind: VmRunKind) -> VmState {
-        let now = std::time::Instant::now();
+    /// Counts an executed statement for `coverage`, `profile`, `cycles` and `history`
+    fn record_execution(&mut self, pc: StmtIdx) {
+        self.exec_counts[pc.0] += 1;
+        self.cycles += self.costs.of(self.stmts[pc.0]);
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(pc);
+        if let Some(trace) = &mut self.trace {
+            trace.push(pc);
+        }
+    }
+
+    /// Undoes the most recent step in the journal, returns false if there is none
+    fn unstep(&mut self) -> bool {
+        let entry = match self.journal.pop_back() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        self.pc = entry.pc;
+        for (r, value) in entry.registers.iter().flatten() {
+            self.registers[r.0] = *value;
+        }
+        match entry.call_stack {
+            CallStackChange::None => {}
+            CallStackChange::Pushed => {
+                self.call_stack.pop();
+            }
+            CallStackChange::Popped(index) => self.call_stack.push(index),
+        }
+        self.random_state = entry.random_state;
+        for breakpoint in &mut self.change_breakpoints {
+            if breakpoint.stmt != entry.next_pc {
+                continue;
+            }
+            // a breakpoint set after the step was not visited by it
+            if let Some(&(_, last_value)) = entry
+                .change_values
+                .iter()
+                .find(|(r, _)| *r == breakpoint.register)
+            {
+                breakpoint.last_value = last_value;
+            }
+        }
+        for breakpoint in &mut self.count_breakpoints {
+            if breakpoint.stmt == entry.next_pc {
+                breakpoint.hits = breakpoint.hits.saturating_sub(1);
+            }
+        }
+        if entry.broke {
+            self.breaks -= 1;
+        }
+        if entry.hit_breakpoint {
+            if let Some(hits) = self.breakpoint_hits.get_mut(&entry.next_pc) {
+                *hits -= 1;
+                if *hits == 0 {
+                    self.breakpoint_hits.remove(&entry.next_pc);
+                }
+            }
+        }
+        if let Stmt::Read(_) = self.stmts[entry.pc.0] {
+            self.input_position -= 1;
+        }
+        self.exec_counts[entry.pc.0] -= 1;
+        self.cycles -= self.costs.of(self.stmts[entry.pc.0]);
+        self.history.pop_back();
+        true
+    }
+
+    /// Records the register values for the change breakpoints at the pc.
+    /// Returns whether one of the registers changed since the last visit
+    fn visit_change_breakpoints(&mut self) -> bool {
+        let mut changed = false;
+        for breakpoint in &mut self.change_breakpoints {
+            if breakpoint.stmt != self.pc {
+                continue;
+            }
+            let value = self.registers[breakpoint.register.0];
+            if breakpoint.last_value.is_some_and(|last| last != value) {
+                changed = true;
+            }
+            breakpoint.last_value = Some(value);
+        }
+        changed
+    }
+
+    /// Counts a hit for the count breakpoints at the pc.
+    /// Returns whether one of them reached its count with this hit
+    fn visit_count_breakpoints(&mut self) -> bool {
+        let mut reached = false;
+        for breakpoint in &mut self.count_breakpoints {
+            if breakpoint.stmt != self.pc {
+                continue;
+            }
+            breakpoint.hits += 1;
+            if breakpoint.hits == breakpoint.count {
+                reached = true;
+            }
+        }
+        reached
+    }
+
+    /// Applies the effect of a statement on the registers, ignoring control flow.
+    /// Fails with `VmState::Overflow` if a register overflows under `OverflowPolicy::Error` and
+    /// with `VmState::NoInput` if a `READ` has no input left
+    fn apply(&mut self, stmt: Stmt) -> Result<(), VmState> {
+        match stmt {
+            Stmt::Inc(r) => {
+                self.registers[r.0] = self
+                    .add(self.registers[r.0], 1)
+                    .ok_or(VmState::Overflow(r))?
+            }
+            Stmt::Dec(r) if self.signed => {
+                self.registers[r.0] = self.registers[r.0].wrapping_sub(1)
+            }
+            // stays at zero like `DEC r value`, `--break-on-underflow` pauses before this instead
+            Stmt::Dec(r) => self.registers[r.0] = self.registers[r.0].saturating_sub(1),
+            Stmt::DecBy(r, value) if self.signed => {
+                self.registers[r.0] = self.registers[r.0].wrapping_sub(value)
+            }
+            Stmt::DecBy(r, value) => {
+                self.registers[r.0] = self.registers[r.0].saturating_sub(value)
+            }
+            Stmt::Swap(a, b) => self.registers.swap(a.0, b.0),
+            Stmt::Rand(r, max) => self.registers[r.0] = (self.next_random() % max as u64) as usize,
+            Stmt::Read(r) => {
+                self.registers[r.0] = *self
+                    .inputs
+                    .get(self.input_position)
+                    .ok_or(VmState::NoInput)?;
+                self.input_position += 1;
+            }
+            Stmt::IsZero(_, _)
+            | Stmt::IsNotZero(_, _)
+            | Stmt::IsEq(_, _, _)
+            | Stmt::Jump(_)
+            | Stmt::Call(_)
+            | Stmt::Ret
+            | Stmt::Stop => {}
+        }
+        Ok(())
+    }
+
+    /// The next number of the splitmix64 generator seeded with `--seed`
+    fn next_random(&mut self) -> u64 {
+        self.random_state = self.random_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.random_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// The register that `stmt` would take below zero, if underflows should break
+    fn underflowing_register(&self, stmt: Stmt) -> Option<Register> {
+        if !self.break_on_underflow || self.signed {
+            return None;
+        }
+        match stmt {
+            Stmt::Dec(r) if self.registers[r.0] == 0 => Some(r),
+            Stmt::DecBy(r, value) if self.registers[r.0] < value => Some(r),
+            _ => None,
+        }
+    }
+
+    /// Adds to a register value according to the overflow policy, `None` means it overflowed
+    fn add(&self, value: usize, amount: usize) -> Option<usize> {
+        let (checked, wrapped, max) = if self.signed {
+            (
+                (value as isize)
+                    .checked_add(amount as isize)
+                    .map(|sum| sum as usize),
+                (value as isize).wrapping_add(amount as isize) as usize,
+                isize::MAX as usize,
+            )
+        } else {
+            (
+                value.checked_add(amount),
+                value.wrapping_add(amount),
+                usize::MAX,
+            )
+        };
+        match (checked, self.overflow) {
+            (Some(sum), _) => Some(sum),
+            (None, OverflowPolicy::Wrap) => Some(wrapped),
+            (None, OverflowPolicy::Saturate) => Some(max),
+            (None, OverflowPolicy::Error) => None,
+        }
+    }
+
+    /// Grows the registers, and the copies taken at breakpoints, so that `r` exists
+    fn ensure_register(&mut self, r: Register) {
+        if r.0 >= self.registers.len() {
+            self.registers.resize(r.0 + 1, 0);
+            self.break_registers.resize(r.0 + 1, 0);
+            self.previous_break_registers.resize(r.0 + 1, 0);
+        }
+    }
+
+    /// Applies a statement outside of the program, without moving the pc
+    fn eval(&mut self, io: &mut Io, stmt: Stmt) {
+        self.ensure_register(Register(max_register(&[stmt])));
+        let old_registers = self.registers.clone();
+        match self.apply(stmt) {
+            Err(VmState::Overflow(r)) => writeln!(io, "error: Register {} overflowed.", r.0),
+            Err(VmState::NoInput) => writeln!(io, "error: No input left to read."),
+            _ => {}
+        }
+        print_register_diff(io, self, &old_registers);
+    }
+
+    /// Runs until the program stops or hits a breakpoint, until `budget` instructions ran, or until
+    /// the condition of `until` is met.
+    /// The time budget is only checked every `TIME_CHECK_INTERVAL` instructions to keep it cheap
+    fn run(
+        &mut self,
+        io: &mut Io,
+        time_kind: VmRunKind,
+        budget: Option<u64>,
+        until: RunUntil,
+    ) -> VmState {
+        let now = Instant::now();
+        let mut instructions = 0_u64;
+        let cycles_before = self.cycles;
+        let mut last_paint = None;
+        if let Some(seen_states) = &mut self.seen_states {
+            seen_states.clear();
+        }
         loop {
-            if let state @ (VmState::Break | VmState::Stop | VmState::OutOfBounds) = self.step() {
+            if self.live_registers
+                && instructions.is_multiple_of(TIME_CHECK_INTERVAL)
+                && last_paint.is_none_or(|last: Instant| last.elapsed() >= LIVE_PAINT_INTERVAL)
+            {
+                paint_live_registers(io, self);
+                last_paint = Some(Instant::now());
+            }
+            let state = if budget == Some(instructions) {
+                VmState::StepLimit
+            } else if instructions.is_multiple_of(TIME_CHECK_INTERVAL)
+                && self
+                    .time_budget
+                    .is_some_and(|time_budget| now.elapsed() >= time_budget)
+            {
+                VmState::TimeLimit
+            } else {
+                match self.check_cycle() {
+                    Some(state) => state,
+                    None => match self.step() {
+                        VmState::Run if self.reached(until) => VmState::Reached,
+                        state => state,
+                    },
+                }
+            };
+            if let VmState::Run | VmState::Break | VmState::Watch(_, _) | VmState::Reached = state {
+                instructions += 1;
+            }
+            if let state @ (VmState::Break
+            | VmState::Stop
+            | VmState::OutOfBounds
+            | VmState::InfiniteLoop(_)
+            | VmState::StepLimit
+            | VmState::TimeLimit
+            | VmState::Watch(_, _)
+            | VmState::Overflow(_)
+            | VmState::Underflow(_)
+            | VmState::EmptyCallStack
+            | VmState::NoInput
+            | VmState::Reached) = state
+            {
+                if last_paint.is_some() {
+                    writeln!(io);
+                }
                 if let VmRunKind::WithTime = time_kind {
-                    println!("Vm ran for {}ms.", now.elapsed().as_millis());
+                    print_run_time(io, instructions, self.cycles - cycles_before, now.elapsed());
                 }
                 return state;
             }
         }
     }
 
+    fn reached(&self, until: RunUntil) -> bool {
+        match until {
+            RunUntil::Break => false,
+            RunUntil::Return(depth) => self.call_stack.len() < depth,
+            RunUntil::Reach(stmt) => self.pc == stmt,
+        }
+    }
+
+    fn check_cycle(&mut self) -> Option<VmState> {
+        let seen_states = self.seen_states.as_mut()?;
+        if seen_states.insert((
+            self.pc,
+            self.registers.clone(),
+            self.call_stack.clone(),
+            self.random_state,
+            self.input_position,
+        )) {
+            None
+        } else {
+            Some(VmState::InfiniteLoop(self.pc))
+        }
+    }
+
+    /// The statement on the line of `search_span`, or the next statement after it if that line is
+    /// blank, a comment or a label. `None` if there is no statement on or after the line
     fn statement_at_span(&self, search_span: Span) -> Option<StmtIdx> {
         self.span
             .iter()
             .position(|span| *span >= search_span)
-            .map(|idx| StmtIdx(idx))
+            .map(StmtIdx)
     }
 }
 
@@ -71,266 +921,3773 @@ enum VmRunKind {
     WithoutTime,
 }
 
+/// What else ends a run besides breakpoints and the program stopping
 #[derive(Debug, Copy, Clone)]
+enum RunUntil {
+    Break,
+    /// The call stack is shallower than the depth
+    Return(usize),
+    /// The pc reaches the statement
+    Reach(StmtIdx),
+}
+
+#[derive(Debug, Clone)]
 enum VmInstruction {
-    Step,
-    Run(VmRunKind),
+    /// Steps once, printing the program view afterwards if set
+    Step(bool),
+    /// Runs, printing the executed statements afterwards if the last field is set
+    Run(VmRunKind, Option<u64>, RunUntil, bool),
     Break(StmtIdx),
-    Set(Register, usize),
+    BreakIfChanged(StmtIdx, Register),
+    BreakCount(StmtIdx, u64),
+    /// Adds the breakpoints read from the breakpoint file
+    RestoreBreakpoints(Vec<StmtIdx>),
+    SetPc(StmtIdx),
+    /// Undoes up to this many steps
+    Back(usize),
+    /// Replaces a statement, with the instruction text to show
+    Patch(StmtIdx, Stmt, String),
+    /// Sets all the registers to the value, adding registers if needed
+    Set(Vec<Register>, usize),
+    StepDiff(bool),
+    StepShow(bool),
+    LiveRegisters(bool),
+    Format(RegisterFormat),
+    LineBase(usize),
+    Eval(Stmt),
+    WatchValue(Register, usize),
+    Unwatch(Register),
+    Load(String),
+    Switch(String),
+    Title(String),
+    Fork,
+    Revert,
     Stop,
+    Exit,
+    /// An unknown command in strict mode or in a sourced script
+    Abort,
 }
 
-pub fn start(program_path: Option<String>) {
-    if let Some(path) = program_path {
-        read_and_run(&path);
-    }
-
-    loop {
-        match loading_input() {
-            LoadInstruction::Quit => return,
-            LoadInstruction::Load(path) => read_and_run(&path),
-        }
-    }
+/// A program passed on the command line
+#[derive(Debug, Clone)]
+pub enum Program {
+    Source(String),
+    Bytecode(String),
 }
 
-fn read_and_run(path: &str) {
-    let path = Path::new(path);
-
-    match std::fs::read_to_string(path) {
-        Ok(content) => match parse::parse(&content, filename(path)) {
-            Ok(stmts) => run(stmts),
-            Err(why) => eprintln!("{}", why),
-        },
-        Err(why) => eprintln!("error while reading file: {}.", why),
-    };
+/// How a debugging session ended
+#[derive(Debug, Copy, Clone)]
+enum SessionEnd {
+    /// All programs finished, go back to loading programs
+    Unload,
+    /// Exit m8db entirely
+    Exit,
+    /// An unknown command in strict mode, exit m8db with an error
+    Abort,
 }
 
-#[derive(Debug, Clone)]
-enum LoadInstruction {
-    Quit,
-    Load(String),
+/// All loaded programs. The active one is being debugged, the others keep their state
+#[derive(Debug)]
+struct Programs {
+    vms: Vec<Vm>,
+    active: usize,
+    /// Don't print informational messages like "Loaded x."
+    quiet: bool,
+    /// Commands defined with `alias`, they are shared by all programs
+    aliases: BTreeMap<String, Vec<String>>,
+    /// The number of the first line for all programs, set with `linebase`
+    line_base: usize,
+    /// Restore the breakpoints saved with `break save` when a program is loaded
+    load_breakpoints: bool,
 }
 
-fn loading_input() -> LoadInstruction {
-    loop {
-        let input = get_input(None);
-        let mut iter = input.split_whitespace();
-        if let Some(str) = iter.next() {
-            match str {
-                "l" | "load" => match iter.next() {
-                    Some(path) => return LoadInstruction::Load(path.to_owned()),
-                    None => println!("error: No file path provided to load from."),
-                },
-                "h" | "help" => print_load_help(),
-                "q" | "quit" => return LoadInstruction::Quit,
-                cmd => println!("error: Unknown command: {}.", cmd),
+impl Programs {
+    fn add(&mut self, io: &mut Io, mut vm: Vm) {
+        vm.line_base = self.line_base;
+        if !self.quiet {
+            writeln!(io, "Loaded {}.", vm.file_name);
+        }
+        if self.load_breakpoints {
+            if let Some(path) = breakpoint_file(&vm).filter(|path| path.exists()) {
+                if let Some(breakpoints) = read_breakpoints(io, &vm, &path) {
+                    if !self.quiet {
+                        writeln!(io, "Restored {} breakpoints.", breakpoints.len());
+                    }
+                    vm.breakpoints = breakpoints;
+                }
             }
         }
+        self.vms.push(vm);
+        self.active = self.vms.len() - 1;
     }
-}
 
-fn filename(path: &Path) -> String {
-    path.file_stem().unwrap().to_str().unwrap().to_owned()
-}
-
-fn run(code: Code) {
-    println!("Loaded {}.", code.file_name);
-    let max_register_index = max_register(&code.stmts);
-    let mut vm = Vm {
-        stmts: code.stmts,
-        span: code.span,
-        code_lines: code.code_lines,
-        file_name: code.file_name,
-        pc: StmtIdx(0),
-        registers: vec![0; max_register_index + 1],
-        breakpoints: vec![],
-    };
+    /// Removes the active program and switches to the most recently loaded one left
+    fn finish_active(&mut self, io: &mut Io) {
+        self.vms.remove(self.active);
+        self.active = self.vms.len().saturating_sub(1);
+        if let Some(vm) = self.vms.get(self.active) {
+            if !self.quiet {
+                writeln!(io, "Switched to {}.", vm.file_name);
+            }
+        }
+    }
 
-    loop {
-        match debug_input(&vm) {
-            VmInstruction::Stop => break,
-            VmInstruction::Run(time_kind) => match vm.run(time_kind) {
-                VmState::Stop => break,
-                VmState::OutOfBounds => {
-                    print_program(&vm);
-                    print_registers(&vm);
-                    eprintln!("error: Program ran out of bounds.");
-                    return;
-                }
-                VmState::Run => {
-                    unreachable!("internal error: Program still running after returning from run.")
-                }
-                _ => {}
-            },
-            VmInstruction::Step => match vm.step() {
-                VmState::Stop => break,
-                VmState::OutOfBounds => {
-                    print_program(&vm);
-                    print_registers(&vm);
-                    eprintln!("error: Program ran out of bounds.");
-                    return;
-                }
-                _ => {}
-            },
-            VmInstruction::Break(line) => {
-                let position = vm.breakpoints.iter().position(|point| *point == line);
-                match position {
-                    None => vm.breakpoints.push(line),
-                    Some(pos) => {
-                        vm.breakpoints.remove(pos);
-                    }
+    fn switch(&mut self, io: &mut Io, name: &str) {
+        match self.vms.iter().position(|vm| vm.file_name == name) {
+            Some(index) => {
+                self.active = index;
+                if !self.quiet {
+                    writeln!(io, "Switched to {}.", name);
                 }
             }
-            VmInstruction::Set(r, value) => vm.registers[r.0] = value,
+            None => writeln!(io, "error: No program named '{}' is loaded.", name),
+        }
+    }
+
+    fn prompt(&self) -> String {
+        let name = &self.vms[self.active].file_name;
+        if self.vms.len() > 1 {
+            format!("{} [{}/{}]", name, self.active + 1, self.vms.len())
+        } else {
+            name.clone()
         }
     }
-    println!("Execution finished.");
 }
 
-fn debug_input(vm: &Vm) -> VmInstruction {
+/// Runs the debugger, returns the process exit code
+pub fn start(io: &mut Io, program: Option<Program>, config: Config) -> i32 {
+    let mut programs = Programs {
+        vms: Vec::new(),
+        active: 0,
+        quiet: config.quiet,
+        aliases: BTreeMap::new(),
+        line_base: parse::DEFAULT_LINE_BASE,
+        load_breakpoints: config.load_breakpoints,
+    };
+    let vm = match program {
+        Some(Program::Source(path)) if path == "-" => {
+            writeln!(io.errors(), "{}", STDIN_DEBUG_ERROR);
+            return 1;
+        }
+        Some(Program::Source(path)) => load(io, &path, &config, programs.line_base),
+        Some(Program::Bytecode(path)) => load_bytecode(io, &path, &config, programs.line_base),
+        None => None,
+    };
+    if let Some(vm) = vm {
+        programs.add(io, vm);
+    }
+
     loop {
-        let input = get_input(Some(&vm.file_name));
-        let mut iter = input.split_whitespace();
-        if let Some(str) = iter.next() {
-            match str {
-                "r" | "register" => print_registers(vm),
-                "p" | "program" => print_program(vm),
-                "h" | "?" | "help" => print_debug_help(),
-                "b" | "break" => match iter.next() {
-                    Some(line_number) => match line_number.parse::<usize>() {
-                        Ok(line_number) => {
-                            let stmt_pos =
-                                match vm.statement_at_span(LineNumber(line_number).span()) {
-                                    Some(pos) => pos,
-                                    None => {
-                                        println!(
-                                            "error: Line number '{}' out of bounds for length {}.",
-                                            line_number,
-                                            vm.code_lines.len()
-                                        );
-                                        continue;
-                                    }
-                                };
-                            return VmInstruction::Break(stmt_pos);
-                        }
-                        Err(_) => println!("error: Invalid argument provided."),
-                    },
-                    None => print_breakpoints(vm),
-                },
-                "set" => match parse_set_command(&mut iter) {
-                    Some((reg, value)) => return VmInstruction::Set(reg, value),
-                    None => println!("error: Invalid arguments provided."),
-                },
-                "c" | "continue" => {
-                    if let Some("time") = iter.next() {
-                        return VmInstruction::Run(VmRunKind::WithTime);
+        if programs.vms.is_empty() {
+            match loading_input(io, &config, programs.line_base) {
+                LoadInstruction::Quit => return 0,
+                LoadInstruction::Abort => return 1,
+                LoadInstruction::Load(path) => {
+                    if let Some(vm) = load(io, &path, &config, programs.line_base) {
+                        programs.add(io, vm);
                     }
-                    return VmInstruction::Run(VmRunKind::WithoutTime);
                 }
-                "s" | "step" => return VmInstruction::Step,
-                "q" | "quit" => return VmInstruction::Stop,
-                cmd => println!("error: Unknown command: {}.", cmd),
+            }
+        } else {
+            match run(io, &mut programs, &config) {
+                SessionEnd::Unload => {}
+                SessionEnd::Exit => return 0,
+                SessionEnd::Abort => return 1,
             }
         }
     }
 }
 
-fn parse_set_command<'a>(iter: &mut impl Iterator<Item = &'a str>) -> Option<(Register, usize)> {
-    let reg = iter.next().and_then(|reg| reg.parse().ok())?;
-    let value = iter.next().and_then(|value| value.parse().ok())?;
-    Some((Register(reg), value))
-}
+/// Tells that `-` can't be debugged, since the program would use up the commands on stdin
+const STDIN_DEBUG_ERROR: &str =
+    "error: The debugger reads its commands from stdin, read a program from '-' with --run or --check-only.";
 
-fn max_register(stmts: &[Stmt]) -> usize {
-    stmts
-        .iter()
-        .map(|stmt| match stmt {
-            Stmt::Inc(r) => r.0,
-            Stmt::Dec(r) => r.0,
-            Stmt::IsZero(r, _) => r.0,
-            Stmt::Jump(_) => 0,
-            Stmt::Stop => 0,
-        })
-        .max()
-        .unwrap_or(0)
+/// Reads the program at `path` like `read_source`, resolves its includes and parses it into a
+/// new VM. Errors count lines from `line_base`
+fn read_program(io: &mut Io, path: &str, config: &Config, line_base: usize) -> Result<Vm, String> {
+    let (path, content) = read_source(io, path, line_base)?;
+    let source = parse::resolve_includes(&content, &path, line_base)?;
+    let code = parse::parse(
+        &source,
+        filename(&path),
+        config.max_register(),
+        line_base,
+        config.strict_labels,
+    )?;
+    let mut vm = Vm::new(code, config);
+    if path != Path::new("-") {
+        vm.source_path = Some(path);
+    }
+    Ok(vm)
 }
 
-fn print_registers(vm: &Vm) {
-    println!("Registers:");
-    for (i, r) in vm.registers.iter().enumerate() {
-        println!("{: >4} : {}", i, r);
+/// Errors count lines from `line_base`
+fn load(io: &mut Io, path: &str, config: &Config, line_base: usize) -> Option<Vm> {
+    if path == "-" {
+        writeln!(io.errors(), "{}", STDIN_DEBUG_ERROR);
+        return None;
     }
-}
 
-fn print_program(vm: &Vm) {
+    match read_program(io, path, config, line_base) {
+        Ok(vm) => {
+            warn_uninitialized_reads(io, &vm, line_base);
+            Some(vm)
+        }
+        Err(why) => {
+            writeln!(io.errors(), "{}", why);
+            None
+        }
+    }
+}
+
+/// Parses the program at `path` and reports whether it is valid, without loading it. Errors count
+/// lines from `line_base`
+fn check(io: &mut Io, path: &str, config: &Config, line_base: usize) -> bool {
+    match read_program(io, path, config, line_base) {
+        Ok(vm) => {
+            warn_uninitialized_reads(io, &vm, line_base);
+            writeln!(
+                io,
+                "{} is valid, {} statements.",
+                vm.file_name,
+                vm.stmts.len()
+            );
+            true
+        }
+        Err(why) => {
+            writeln!(io.errors(), "{}", why);
+            false
+        }
+    }
+}
+
+/// Checks every program in `paths` without the debugger, returns 1 if any of them is invalid
+pub fn check_programs(io: &mut Io, paths: &[String], config: &Config) -> i32 {
+    let mut failed = 0;
+    for path in paths {
+        if !check(io, path, config, parse::DEFAULT_LINE_BASE) {
+            writeln!(io.errors(), "error: {} is invalid.", path);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        writeln!(
+            io.errors(),
+            "error: {} of {} programs are invalid.",
+            failed,
+            paths.len()
+        );
+        return 1;
+    }
+    0
+}
+
+fn warn_uninitialized_reads(io: &mut Io, vm: &Vm, line_base: usize) {
+    for warning in parse::uninitialized_reads(&vm.stmts, &vm.span, line_base) {
+        writeln!(io.errors(), "{}", warning);
+    }
+}
+
+/// Reads the values for `READ` from `--input-file`, one number per line. Blank lines are skipped
+pub fn read_inputs(path: &str) -> Result<Vec<usize>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|why| format!("error while reading file '{}': {}.", path, why))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            parse::parse_number(line.trim())
+                .map_err(|why| format!("error on line '{}' of '{}': {}.", i + 1, path, why))
+        })
+        .collect()
+}
+
+fn load_bytecode(io: &mut Io, path: &str, config: &Config, line_base: usize) -> Option<Vm> {
+    let path = Path::new(path);
+
+    match std::fs::read(path) {
+        Ok(bytes) => match bytecode::decode(&bytes, line_base) {
+            Ok(bytecode) => {
+                if let Err(why) = parse::validate_registers(
+                    &bytecode.stmts,
+                    &bytecode.span,
+                    config.max_register(),
+                    line_base,
+                ) {
+                    writeln!(io.errors(), "{}", why);
+                    return None;
+                }
+                let source = bytecode::render(&bytecode);
+                let code = Code {
+                    stmts: bytecode.stmts,
+                    span: bytecode.span,
+                    code_lines: source.lines().collect(),
+                    file_name: filename(path),
+                    groups: Vec::new(),
+                    labels: Vec::new(),
+                };
+                return Some(Vm::new(code, config));
+            }
+            Err(why) => writeln!(io.errors(), "{}", why),
+        },
+        Err(why) => writeln!(io.errors(), "error while reading file: {}.", why),
+    };
+    None
+}
+
+/// Runs the program at `path` to completion without the debugger and prints the registers.
+/// Returns the process exit code for how the program ended:
+///
+/// | code | meaning                                         |
+/// |------|-------------------------------------------------|
+/// | 0    | the program reached `STOP`                      |
+/// | 1    | the file could not be read or parsed            |
+/// | 2    | the program ran out of bounds                   |
+/// | 3    | an infinite loop was detected                   |
+/// | 4    | a register overflowed with `--overflow error`   |
+/// | 5    | a `DEC` underflowed with `--break-on-underflow` |
+/// | 6    | a `RET` was reached without a `CALL`            |
+/// | 7    | the `--time-budget` ran out                     |
+/// | 8    | a `READ` had no input left                      |
+pub fn run_program(io: &mut Io, path: &str, config: &Config) -> i32 {
+    let mut vm = match read_program(io, path, config, parse::DEFAULT_LINE_BASE) {
+        Ok(vm) => vm,
+        Err(why) => {
+            writeln!(io.errors(), "{}", why);
+            return 1;
+        }
+    };
+
+    warn_uninitialized_reads(io, &vm, parse::DEFAULT_LINE_BASE);
+    let state = vm.run(io, VmRunKind::WithoutTime, None, RunUntil::Break);
+    print_registers(io, &vm, RegisterFilter::All, vm.register_format);
+    match state {
+        VmState::Stop => 0,
+        VmState::OutOfBounds => {
+            writeln!(io.errors(), "error: Program ran out of bounds.");
+            2
+        }
+        VmState::InfiniteLoop(stmt) => {
+            writeln!(
+                io.errors(),
+                "error: Program entered an infinite loop at line {}.",
+                vm.line(vm.span[stmt.0])
+            );
+            3
+        }
+        VmState::TimeLimit => {
+            writeln!(io.errors(), "error: Program ran out of time.");
+            7
+        }
+        VmState::Overflow(r) => {
+            writeln!(
+                io.errors(),
+                "error: Register {} overflowed on line {}.",
+                r.0,
+                vm.line(vm.span[vm.pc.0])
+            );
+            4
+        }
+        VmState::Underflow(r) => {
+            writeln!(
+                io.errors(),
+                "error: Register {} underflowed on line {}.",
+                r.0,
+                vm.line(vm.span[vm.pc.0])
+            );
+            5
+        }
+        VmState::EmptyCallStack => {
+            writeln!(
+                io.errors(),
+                "error: RET without a CALL on line {}.",
+                vm.line(vm.span[vm.pc.0])
+            );
+            6
+        }
+        VmState::NoInput => {
+            writeln!(
+                io.errors(),
+                "error: No input left to read on line {}.",
+                vm.line(vm.span[vm.pc.0])
+            );
+            8
+        }
+        // there is no instruction budget without the debugger
+        VmState::Run
+        | VmState::Break
+        | VmState::Watch(_, _)
+        | VmState::Reached
+        | VmState::StepLimit => {
+            unreachable!("internal error: Program still running after returning from run.")
+        }
+    }
+}
+
+/// Parses the program at `input` and writes its resolved bytecode to `output`
+pub fn assemble(io: &mut Io, input: &str, output: &str, config: &Config) -> Result<(), String> {
+    let vm = read_program(io, input, config, parse::DEFAULT_LINE_BASE)?;
+    let bytes = bytecode::encode(&vm.stmts, &vm.span, vm.code_lines.len());
+    std::fs::write(output, bytes).map_err(|why| format!("error while writing file: {}.", why))
+}
+
+#[derive(Debug, Clone)]
+enum LoadInstruction {
+    Quit,
+    Load(String),
+    /// An unknown command in strict mode
+    Abort,
+}
+
+/// Reads commands while no program is loaded. Errors count lines from `line_base`
+fn loading_input(io: &mut Io, config: &Config, line_base: usize) -> LoadInstruction {
+    loop {
+        let input = match get_input(io, None) {
+            Some(input) => input,
+            None => return LoadInstruction::Quit,
+        };
+        let mut iter = input.split_whitespace();
+        if let Some(str) = iter.next() {
+            match str {
+                "l" | "load" => match iter.next() {
+                    Some(path) => return LoadInstruction::Load(path.to_owned()),
+                    None => writeln!(io, "error: No file path provided to load from."),
+                },
+                "check" => match iter.next() {
+                    Some("-") => writeln!(io.errors(), "{}", STDIN_DEBUG_ERROR),
+                    Some(path) => {
+                        check(io, path, config, line_base);
+                    }
+                    None => writeln!(io, "error: No file path provided to check."),
+                },
+                "h" | "help" => print_load_help(io),
+                "version" => print_version(io),
+                "q" | "quit" => return LoadInstruction::Quit,
+                comment if comment.starts_with('#') => {}
+                cmd => {
+                    writeln!(io, "error: Unknown command: {}.", cmd);
+                    if config.strict {
+                        return LoadInstruction::Abort;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Expands a leading `~` to the home directory and `$VAR` or `${VAR}` to environment variables
+fn expand_path(path: &str) -> Result<String, String> {
+    let var = |name: &str| {
+        std::env::var(name)
+            .map_err(|_| format!("error: Environment variable '{}' is not set.", name))
+    };
+
+    let mut expanded = String::new();
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&var("HOME")?);
+        rest = &rest[1..];
+    }
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => braced
+                .split_once('}')
+                .ok_or_else(|| format!("error: Unclosed '${{' in path '{}'.", path))?,
+            None => {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            expanded.push_str(&var(name)?);
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Reads the program at `path` after `expand_path`, or from stdin if the path is `-`.
+/// Returns the expanded path, which includes are relative to, with the source. Invalid UTF-8 is
+/// reported on a line counting from `line_base`
+fn read_source(io: &mut Io, path: &str, line_base: usize) -> Result<(PathBuf, String), String> {
+    let expanded = expand_path(path)?;
+    let bytes = if expanded == "-" {
+        let mut bytes = Vec::new();
+        io.input.read_to_end(&mut bytes).map(|_| bytes)
+    } else {
+        std::fs::read(&expanded)
+    };
+    match bytes.and_then(|bytes| parse::source_from_bytes(bytes, line_base)) {
+        Ok(content) => Ok((PathBuf::from(expanded), content)),
+        Err(why) if expanded == path => Err(format!("error while reading file: {}.", why)),
+        // mention the expanded path, it may not be what the user expected
+        Err(why) => Err(format!("error while reading file '{}': {}.", expanded, why)),
+    }
+}
+
+fn filename(path: &Path) -> String {
+    if path == Path::new("-") {
+        return "<stdin>".to_owned();
+    }
+    path.file_stem().unwrap().to_str().unwrap().to_owned()
+}
+
+fn run(io: &mut Io, programs: &mut Programs, config: &Config) -> SessionEnd {
+    let mut input_queue = VecDeque::new();
+    loop {
+        let prompt = programs.prompt();
+        let instruction = debug_input(
+            io,
+            &programs.vms[programs.active],
+            &prompt,
+            &mut input_queue,
+            &mut programs.aliases,
+            config.strict,
+        );
+        let vm = &mut programs.vms[programs.active];
+        let finished = match instruction {
+            VmInstruction::Stop => {
+                if vm.dirty
+                    && !config.no_confirm
+                    && !confirm(io, "Discard current debugging session?")
+                {
+                    false
+                } else {
+                    if config.summary {
+                        print_summary(io, vm);
+                    }
+                    if !config.quiet {
+                        writeln!(io, "Execution finished.");
+                    }
+                    true
+                }
+            }
+            VmInstruction::Exit => return SessionEnd::Exit,
+            VmInstruction::Abort => return SessionEnd::Abort,
+            VmInstruction::Run(time_kind, budget, until, trace) => {
+                if trace {
+                    vm.trace = Some(Vec::new());
+                }
+                let state = vm.run(io, time_kind, budget, until);
+                if let Some(trace) = vm.trace.take() {
+                    print_trace(io, vm, &trace);
+                }
+                match state {
+                    VmState::Stop => {
+                        if !config.quiet {
+                            writeln!(io, "Execution finished.");
+                        }
+                        true
+                    }
+                    VmState::OutOfBounds => {
+                        print_program(io, vm);
+                        print_registers(io, vm, RegisterFilter::All, vm.register_format);
+                        writeln!(io.errors(), "error: Program ran out of bounds.");
+                        true
+                    }
+                    VmState::InfiniteLoop(stmt) => {
+                        print_program(io, vm);
+                        writeln!(
+                            io,
+                            "Program entered an infinite loop at line {}.",
+                            vm.line(vm.span[stmt.0])
+                        );
+                        false
+                    }
+                    VmState::StepLimit => {
+                        writeln!(io, "Ran {} instructions.", budget.unwrap_or_default());
+                        false
+                    }
+                    VmState::TimeLimit => {
+                        print_program(io, vm);
+                        writeln!(io, "Stopped after the time budget ran out.");
+                        false
+                    }
+                    VmState::Watch(r, value) => {
+                        print_watch(io, vm, r, value);
+                        false
+                    }
+                    VmState::Overflow(r) => {
+                        print_overflow(io, vm, r);
+                        false
+                    }
+                    VmState::Underflow(r) => {
+                        print_underflow(io, vm, r);
+                        false
+                    }
+                    VmState::EmptyCallStack => {
+                        print_empty_call_stack(io, vm);
+                        false
+                    }
+                    VmState::NoInput => {
+                        print_no_input(io, vm);
+                        false
+                    }
+                    VmState::Reached => {
+                        print_program(io, vm);
+                        false
+                    }
+                    VmState::Run => {
+                        unreachable!(
+                            "internal error: Program still running after returning from run."
+                        )
+                    }
+                    VmState::Break => false,
+                }
+            }
+            VmInstruction::Step(show) => {
+                let old_registers = vm.step_diff.then(|| vm.registers.clone());
+                let branch = match vm.stmts.get(vm.pc.0) {
+                    Some(
+                        &stmt @ (Stmt::IsZero(r, _) | Stmt::IsNotZero(r, _) | Stmt::IsEq(r, _, _)),
+                    ) => Some((stmt, vm.registers[r.0])),
+                    _ => None,
+                };
+                match vm.step() {
+                    VmState::Stop => {
+                        if !config.quiet {
+                            writeln!(io, "Execution finished.");
+                        }
+                        true
+                    }
+                    VmState::OutOfBounds => {
+                        print_program(io, vm);
+                        print_registers(io, vm, RegisterFilter::All, vm.register_format);
+                        writeln!(io.errors(), "error: Program ran out of bounds.");
+                        true
+                    }
+                    state => {
+                        if let Some((stmt, value)) = branch {
+                            print_branch(io, vm, stmt, value);
+                        }
+                        if let Some(old_registers) = old_registers {
+                            print_step_diff(io, vm, &old_registers);
+                        }
+                        if show {
+                            print_program(io, vm);
+                        }
+                        match state {
+                            VmState::Watch(r, value) => print_watch(io, vm, r, value),
+                            VmState::Overflow(r) => print_overflow(io, vm, r),
+                            VmState::Underflow(r) => print_underflow(io, vm, r),
+                            VmState::EmptyCallStack => print_empty_call_stack(io, vm),
+                            VmState::NoInput => print_no_input(io, vm),
+                            _ => {}
+                        }
+                        false
+                    }
+                }
+            }
+            VmInstruction::RestoreBreakpoints(breakpoints) => {
+                writeln!(io, "Restored {} breakpoints.", breakpoints.len());
+                for stmt in breakpoints {
+                    if !vm.breakpoints.contains(&stmt) {
+                        vm.breakpoints.push(stmt);
+                    }
+                }
+                vm.dirty = true;
+                false
+            }
+            VmInstruction::Break(line) => {
+                let position = vm.breakpoints.iter().position(|point| *point == line);
+                match position {
+                    None => vm.breakpoints.push(line),
+                    Some(pos) => {
+                        vm.breakpoints.remove(pos);
+                        vm.breakpoint_hits.remove(&line);
+                    }
+                }
+                vm.dirty = true;
+                false
+            }
+            VmInstruction::BreakIfChanged(stmt, register) => {
+                let position = vm
+                    .change_breakpoints
+                    .iter()
+                    .position(|point| point.stmt == stmt && point.register == register);
+                match position {
+                    None => vm.change_breakpoints.push(ChangeBreakpoint {
+                        stmt,
+                        register,
+                        last_value: None,
+                    }),
+                    Some(pos) => {
+                        vm.change_breakpoints.remove(pos);
+                    }
+                }
+                vm.dirty = true;
+                false
+            }
+            VmInstruction::BreakCount(stmt, count) => {
+                let position = vm
+                    .count_breakpoints
+                    .iter()
+                    .position(|point| point.stmt == stmt && point.count == count);
+                match position {
+                    None => vm.count_breakpoints.push(CountBreakpoint {
+                        stmt,
+                        count,
+                        hits: 0,
+                    }),
+                    Some(pos) => {
+                        vm.count_breakpoints.remove(pos);
+                    }
+                }
+                vm.dirty = true;
+                false
+            }
+            VmInstruction::Set(registers, value) => {
+                for r in registers {
+                    vm.ensure_register(r);
+                    vm.registers[r.0] = value;
+                }
+                vm.journal.clear();
+                vm.dirty = true;
+                false
+            }
+            VmInstruction::SetPc(stmt) => {
+                vm.pc = stmt;
+                vm.journal.clear();
+                vm.dirty = true;
+                print_program(io, vm);
+                false
+            }
+            VmInstruction::Patch(stmt_idx, stmt, text) => {
+                for r in stmt.registers() {
+                    vm.ensure_register(r);
+                }
+                let span = vm.span[stmt_idx.0];
+                let line = &vm.code_lines[span.0];
+                let patched = if vm.span.iter().filter(|other| **other == span).count() == 1 {
+                    let label = parse::tokenize_line(span, line, vm.line_base)
+                        .ok()
+                        .and_then(|tokens| tokens.label)
+                        .map(|label| format!(".{} ", label))
+                        .unwrap_or_default();
+                    format!("{}{} # patched", label, text)
+                } else {
+                    format!("{} # patched *{}: {}", line, stmt_idx.0, text)
+                };
+                vm.code_lines[span.0] = patched;
+                vm.stmts[stmt_idx.0] = stmt;
+                vm.journal.clear();
+                vm.dirty = true;
+                writeln!(io, "Patched line {}.", vm.line(span));
+                false
+            }
+            VmInstruction::Back(count) => {
+                let undone = (0..count).take_while(|_| vm.unstep()).count();
+                if undone < count {
+                    writeln!(
+                        io,
+                        "Stepped back {} of {} statements, there are no older steps to undo.",
+                        undone, count
+                    );
+                } else {
+                    writeln!(io, "Stepped back {} statements.", undone);
+                }
+                if undone > 0 {
+                    vm.dirty = true;
+                    print_program(io, vm);
+                }
+                false
+            }
+            VmInstruction::LineBase(base) => {
+                programs.line_base = base;
+                for vm in &mut programs.vms {
+                    vm.line_base = base;
+                }
+                false
+            }
+            VmInstruction::StepDiff(enabled) => {
+                vm.step_diff = enabled;
+                false
+            }
+            VmInstruction::StepShow(enabled) => {
+                vm.step_show = enabled;
+                false
+            }
+            VmInstruction::LiveRegisters(enabled) => {
+                vm.live_registers = enabled;
+                false
+            }
+            VmInstruction::Format(format) => {
+                vm.register_format = format;
+                false
+            }
+            VmInstruction::Eval(stmt) => {
+                vm.eval(io, stmt);
+                vm.journal.clear();
+                vm.dirty = true;
+                false
+            }
+            VmInstruction::WatchValue(r, value) => {
+                if !vm.value_watches.contains(&(r, value)) {
+                    vm.value_watches.push((r, value));
+                }
+                vm.dirty = true;
+                false
+            }
+            VmInstruction::Unwatch(r) => {
+                vm.value_watches.retain(|(watched, _)| *watched != r);
+                vm.dirty = true;
+                false
+            }
+            VmInstruction::Load(path) => {
+                if let Some(vm) = load(io, &path, config, programs.line_base) {
+                    programs.add(io, vm);
+                }
+                false
+            }
+            VmInstruction::Switch(name) => {
+                programs.switch(io, &name);
+                false
+            }
+            VmInstruction::Title(name) => {
+                vm.file_name = name;
+                false
+            }
+            VmInstruction::Fork => {
+                let mut forks = std::mem::take(&mut vm.forks);
+                forks.push(vm.clone());
+                vm.forks = forks;
+                writeln!(io, "Saved fork {}.", vm.forks.len());
+                false
+            }
+            VmInstruction::Revert => {
+                let mut forks = std::mem::take(&mut vm.forks);
+                match forks.pop() {
+                    Some(fork) => {
+                        *vm = fork;
+                        writeln!(io, "Reverted to fork {}.", forks.len() + 1);
+                        print_program(io, vm);
+                    }
+                    None => writeln!(io, "error: No fork to revert to."),
+                }
+                vm.forks = forks;
+                false
+            }
+        };
+
+        if finished {
+            programs.finish_active(io);
+            if programs.vms.is_empty() {
+                return SessionEnd::Unload;
+            }
+        }
+    }
+}
+
+/// Where a queued command came from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Queued {
+    /// A line of a file read with `source`
+    Script,
+    /// A command of an expanded alias, it acts like a typed command
+    Alias,
+}
+
+fn debug_input(
+    io: &mut Io,
+    vm: &Vm,
+    prompt: &str,
+    input_queue: &mut VecDeque<(String, Queued)>,
+    aliases: &mut BTreeMap<String, Vec<String>>,
+    strict: bool,
+) -> VmInstruction {
+    loop {
+        let (input, from_script) = match input_queue.pop_front() {
+            Some((line, queued)) => {
+                writeln!(io, "(m8db - {}) {}", prompt, line);
+                (line, queued == Queued::Script)
+            }
+            None => match get_input(io, Some(prompt)) {
+                Some(input) => (input, false),
+                None => return VmInstruction::Exit,
+            },
+        };
+        let first = input.split_whitespace().next().unwrap_or_default();
+        if first == "alias" {
+            define_alias(io, aliases, &input);
+            continue;
+        }
+        if let Some(commands) = aliases.get(first) {
+            for command in commands.iter().rev() {
+                input_queue.push_front((command.clone(), Queued::Alias));
+            }
+            continue;
+        }
+        // a sourced script is always strict, but only the script is aborted
+        match debug_command(io, vm, &input, input_queue, strict || from_script) {
+            Some(VmInstruction::Abort) if !strict => {
+                writeln!(io, "error: Aborted the script after an unknown command.");
+                input_queue.clear();
+            }
+            Some(instruction) => return instruction,
+            None => {}
+        }
+    }
+}
+
+/// Handles `alias <name> = <command>; <command>...`, or lists the aliases without arguments
+fn define_alias(io: &mut Io, aliases: &mut BTreeMap<String, Vec<String>>, input: &str) {
+    let definition = input.trim_start()["alias".len()..].trim();
+    if definition.is_empty() {
+        for (name, commands) in aliases.iter() {
+            writeln!(io, "{} = {}", name, commands.join("; "));
+        }
+        return;
+    }
+    let (name, commands) = match definition.split_once('=') {
+        Some((name, commands)) if !name.trim().is_empty() => (name.trim(), commands),
+        _ => {
+            writeln!(io, "error: Expected 'alias <name> = <commands>'.");
+            return;
+        }
+    };
+    if name.contains(char::is_whitespace) || name == "alias" {
+        writeln!(io, "error: Invalid alias name: {}.", name);
+        return;
+    }
+    let commands = commands
+        .split(';')
+        .map(str::trim)
+        .filter(|command| !command.is_empty())
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    if commands.is_empty() {
+        aliases.remove(name);
+        return;
+    }
+    // aliases can't use aliases, so expanding one can never loop
+    let nested = commands.iter().find_map(|command| {
+        let first = command.split_whitespace().next().unwrap_or_default();
+        (first == name || first == "alias" || aliases.contains_key(first)).then_some(first)
+    });
+    if let Some(nested) = nested {
+        writeln!(io, "error: An alias can't use the alias '{}'.", nested);
+        return;
+    }
+    aliases.insert(name.to_owned(), commands);
+}
+
+/// Handles a single line of debugger input. Returns the instruction for the VM, if there is one
+fn debug_command(
+    io: &mut Io,
+    vm: &Vm,
+    input: &str,
+    input_queue: &mut VecDeque<(String, Queued)>,
+    strict: bool,
+) -> Option<VmInstruction> {
+    let mut iter = input.split_whitespace();
+    if let Some(str) = iter.next() {
+        match str {
+            "r" | "register" => {
+                let mut filter = RegisterFilter::All;
+                let mut format = vm.register_format;
+                while let Some(arg) = iter.next() {
+                    match arg {
+                        "nz" | "nonzero" => filter = RegisterFilter::NonZero,
+                        "only" | "--only" => {
+                            let registers = match iter.next() {
+                                Some(list) => parse_register_list(list, vm.max_register),
+                                None => {
+                                    writeln!(io, "error: No registers provided.");
+                                    return None;
+                                }
+                            };
+                            match registers {
+                                Some(registers) => {
+                                    if let Some(r) =
+                                        registers.iter().find(|r| r.0 >= vm.registers.len())
+                                    {
+                                        writeln!(io, "error: Register '{}' out of bounds.", r.0);
+                                        return None;
+                                    }
+                                    filter = RegisterFilter::Only(registers);
+                                }
+                                None => {
+                                    writeln!(io, "error: Invalid register list provided.");
+                                    return None;
+                                }
+                            }
+                        }
+                        "--json-file" => {
+                            match iter.next() {
+                                Some(path) => match std::fs::write(path, registers_json(vm)) {
+                                    Ok(()) => writeln!(io, "Wrote registers to {}.", path),
+                                    Err(why) => writeln!(io, "error while writing file: {}.", why),
+                                },
+                                None => writeln!(io, "error: No file path provided to write to."),
+                            }
+                            return None;
+                        }
+                        "diff-since-break" => {
+                            writeln!(io, "Changes since the previous breakpoint:");
+                            print_register_diff(io, vm, &vm.previous_break_registers);
+                            return None;
+                        }
+                        "group" => match iter.next() {
+                            Some(name) => match vm.groups.iter().find(|group| group.name == name) {
+                                Some(group) => {
+                                    filter = RegisterFilter::Range(group.start, group.end)
+                                }
+                                None => {
+                                    writeln!(io, "error: Unknown register group: {}.", name);
+                                    return None;
+                                }
+                            },
+                            None => {
+                                writeln!(io, "error: No group name provided.");
+                                return None;
+                            }
+                        },
+                        format_name => match RegisterFormat::parse(format_name) {
+                            Some(parsed) => format = parsed,
+                            None => {
+                                writeln!(io, "error: Unknown register filter: {}.", arg);
+                                return None;
+                            }
+                        },
+                    }
+                }
+                print_registers(io, vm, filter, format);
+            }
+            "p" | "program" => match iter.next() {
+                None => print_program(io, vm),
+                Some("compact") => print_compact_program(io, vm),
+                Some(arg) => writeln!(io, "error: Unknown argument: {}.", arg),
+            },
+            "examine" | "x" => {
+                let start = iter.next().map(parse::parse_number::<usize>);
+                let count = iter.next().map(parse::parse_number::<usize>);
+                match (start, count.unwrap_or(Ok(EXAMINE_LINES))) {
+                    (None, _) => writeln!(io, "error: No line number provided."),
+                    (Some(Ok(line_number)), Ok(count)) => match vm.span_of(line_number) {
+                        Some(span) if span.0 < vm.code_lines.len() => {
+                            print_examine(io, vm, span, count)
+                        }
+                        _ => writeln!(
+                            io,
+                            "error: Line number '{}' out of bounds for length {}.",
+                            line_number,
+                            vm.code_lines.len()
+                        ),
+                    },
+                    _ => writeln!(io, "error: Invalid argument provided."),
+                }
+            }
+            "disasm" => match iter.next() {
+                None => print_disasm(io, vm, false),
+                Some("--with-counts") => print_disasm(io, vm, true),
+                Some(arg) => writeln!(io, "error: Unknown argument: {}.", arg),
+            },
+            "tokens" => match iter.next().map(parse::parse_number) {
+                Some(Ok(line_number)) if vm.span_of(line_number).is_some() => {
+                    print_tokens(io, vm, line_number)
+                }
+                Some(_) => writeln!(io, "error: Invalid argument provided."),
+                None => writeln!(io, "error: No line number provided."),
+            },
+            "whatis" => match iter.next().map(parse::parse_number) {
+                Some(Ok(line_number)) if vm.span_of(line_number).is_some() => {
+                    print_whatis(io, vm, line_number)
+                }
+                Some(_) => writeln!(io, "error: Invalid argument provided."),
+                None => writeln!(io, "error: No line number provided."),
+            },
+            "find" => match iter.next() {
+                Some(mnemonic) => print_find(io, vm, mnemonic),
+                None => writeln!(io, "error: No mnemonic provided."),
+            },
+            "coverage" => print_coverage(io, vm),
+            "stats" => print_stats(io, vm),
+            "target" => print_target(io, vm),
+            "linebase" => match iter.next() {
+                Some("0") => return Some(VmInstruction::LineBase(0)),
+                Some("1") => return Some(VmInstruction::LineBase(1)),
+                _ => writeln!(io, "error: Expected '0' or '1'."),
+            },
+            "pc" => match (iter.next(), iter.next()) {
+                (None, _) => writeln!(io, "pc: {}", describe_target(vm, vm.pc)),
+                (Some("="), Some(index)) => match parse::parse_number::<usize>(index) {
+                    Ok(index) if index < vm.stmts.len() => {
+                        return Some(VmInstruction::SetPc(StmtIdx(index)))
+                    }
+                    Ok(index) => writeln!(
+                        io,
+                        "error: Statement '{}' out of bounds for length {}.",
+                        index,
+                        vm.stmts.len()
+                    ),
+                    Err(_) => writeln!(io, "error: Invalid argument provided."),
+                },
+                _ => writeln!(io, "error: Expected 'pc = <index>'."),
+            },
+            "history" => match iter.next().map(parse::parse_number) {
+                None => print_history(io, vm, 10),
+                Some(Ok(count)) => print_history(io, vm, count),
+                Some(Err(_)) => writeln!(io, "error: Invalid argument provided."),
+            },
+            "profile" => match (iter.next(), iter.next().map(parse::parse_number)) {
+                (None, _) => print_profile(io, vm, 5),
+                (Some("top"), Some(Ok(count))) => print_profile(io, vm, count),
+                _ => writeln!(io, "error: Expected 'top <n>'."),
+            },
+            "bt" | "backtrace" => print_backtrace(io, vm),
+            "cycles" => writeln!(io, "{} cycles.", vm.cycles),
+            "ls" | "list" => match (iter.next(), iter.next()) {
+                (None, _) => print_list(io, vm, None),
+                (Some(start), Some(end)) => match (
+                    parse::parse_number(start).map(|start| vm.span_of(start)),
+                    parse::parse_number(end).map(|end| vm.span_of(end)),
+                ) {
+                    (Ok(Some(start)), Ok(Some(end))) if start <= end => {
+                        print_list(io, vm, Some((start, end)))
+                    }
+                    _ => writeln!(io, "error: Invalid line range provided."),
+                },
+                (Some(_), None) => writeln!(io, "error: No end line provided."),
+            },
+            "h" | "?" | "help" => print_debug_help(io),
+            "version" => print_version(io),
+            "b" | "break" => match iter.next() {
+                Some("stats") => print_breakpoint_stats(io, vm),
+                Some("save") => match breakpoint_file(vm) {
+                    Some(path) => {
+                        let lines = vm
+                            .breakpoints
+                            .iter()
+                            .map(|stmt| {
+                                // the file doesn't depend on `linebase`
+                                let line = vm.span[stmt.0].line_number(parse::DEFAULT_LINE_BASE);
+                                format!("{}\n", line)
+                            })
+                            .collect::<String>();
+                        match std::fs::write(&path, lines) {
+                            Ok(()) => writeln!(
+                                io,
+                                "Saved {} breakpoints to {}.",
+                                vm.breakpoints.len(),
+                                path.display()
+                            ),
+                            Err(why) => writeln!(io, "error while writing file: {}.", why),
+                        }
+                    }
+                    None => writeln!(
+                        io,
+                        "error: Only programs loaded from a file have a breakpoint file."
+                    ),
+                },
+                Some("load") => match breakpoint_file(vm) {
+                    Some(path) => {
+                        if let Some(breakpoints) = read_breakpoints(io, vm, &path) {
+                            return Some(VmInstruction::RestoreBreakpoints(breakpoints));
+                        }
+                    }
+                    None => writeln!(
+                        io,
+                        "error: Only programs loaded from a file have a breakpoint file."
+                    ),
+                },
+                Some(index) if index.starts_with('*') => {
+                    match parse::parse_number::<usize>(&index[1..]) {
+                        Ok(index) if index < vm.stmts.len() => {
+                            return break_instruction(io, vm, StmtIdx(index), &mut iter);
+                        }
+                        Ok(index) => writeln!(
+                            io,
+                            "error: Statement '{}' out of bounds for length {}.",
+                            index,
+                            vm.stmts.len()
+                        ),
+                        Err(_) => writeln!(io, "error: Invalid argument provided."),
+                    }
+                }
+                Some(line_number) => {
+                    if let Some(stmt) = line_statement(io, vm, line_number) {
+                        return break_instruction(io, vm, stmt, &mut iter);
+                    }
+                }
+                None => print_breakpoints(io, vm),
+            },
+            "set" => match (
+                iter.next()
+                    .and_then(|registers| parse_register_list(registers, vm.max_register)),
+                iter.next().and_then(|value| parse_value(value, vm.signed)),
+            ) {
+                (Some(registers), Some(value)) => {
+                    return Some(VmInstruction::Set(registers, value))
+                }
+                _ => writeln!(io, "error: Invalid arguments provided."),
+            },
+            "edit" => match parse_edit_command(&mut iter, vm.signed) {
+                Some((r, _, _)) if r.0 >= vm.registers.len() => {
+                    writeln!(io, "error: Register '{}' out of bounds.", r.0)
+                }
+                Some((r, op, amount)) => {
+                    let value = edit_register(vm, vm.registers[r.0], op, amount);
+                    return Some(VmInstruction::Set(vec![r], value));
+                }
+                None => writeln!(io, "error: Expected '<register> <+=|-=|*=> <value>'."),
+            },
+            "c" | "continue" => {
+                let mut time_kind = VmRunKind::WithoutTime;
+                let mut budget = None;
+                let mut until = RunUntil::Break;
+                let mut trace = false;
+                while let Some(arg) = iter.next() {
+                    match arg {
+                        "time" => time_kind = VmRunKind::WithTime,
+                        "trace" | "--trace" => trace = true,
+                        "to" => match iter.next() {
+                            Some(line_number) => match line_statement(io, vm, line_number) {
+                                Some(stmt) => until = RunUntil::Reach(stmt),
+                                None => return None,
+                            },
+                            None => {
+                                writeln!(io, "error: No line number provided.");
+                                return None;
+                            }
+                        },
+                        count => match count.parse() {
+                            Ok(count) => budget = Some(count),
+                            Err(_) => {
+                                writeln!(io, "error: Invalid argument provided.");
+                                return None;
+                            }
+                        },
+                    }
+                }
+                return Some(VmInstruction::Run(time_kind, budget, until, trace));
+            }
+            "finish" | "until-return" => {
+                if vm.call_stack.is_empty() {
+                    writeln!(io, "error: Not inside a subroutine.");
+                } else {
+                    return Some(VmInstruction::Run(
+                        VmRunKind::WithoutTime,
+                        None,
+                        RunUntil::Return(vm.call_stack.len()),
+                        false,
+                    ));
+                }
+            }
+            "back" | "step-back" => match iter.next().map(parse::parse_number::<usize>) {
+                None => return Some(VmInstruction::Back(1)),
+                Some(Ok(count)) if count > 0 => return Some(VmInstruction::Back(count)),
+                Some(_) => writeln!(io, "error: Expected a number of steps above zero."),
+            },
+            "s" | "step" => match iter.next() {
+                None => return Some(VmInstruction::Step(vm.step_show)),
+                Some("--show") => return Some(VmInstruction::Step(true)),
+                Some("--silent") => return Some(VmInstruction::Step(false)),
+                Some(_) => writeln!(io, "error: Expected '--show' or '--silent'."),
+            },
+            "watch" => match iter.next() {
+                Some("value") => match parse_set_command(&mut iter, vm.signed) {
+                    Some((r, _)) if r.0 >= vm.registers.len() => {
+                        writeln!(io, "error: Register '{}' out of bounds.", r.0)
+                    }
+                    Some((r, value)) => return Some(VmInstruction::WatchValue(r, value)),
+                    None => writeln!(io, "error: Invalid arguments provided."),
+                },
+                None => print_watches(io, vm),
+                _ => writeln!(io, "error: Expected 'value'."),
+            },
+            "unwatch" => match iter.next().map(parse::parse_number) {
+                Some(Ok(r)) if vm.value_watches.iter().any(|(watched, _)| watched.0 == r) => {
+                    return Some(VmInstruction::Unwatch(Register(r)))
+                }
+                Some(Ok(r)) => writeln!(io, "error: No watchpoint on register '{}'.", r),
+                Some(Err(_)) => writeln!(io, "error: Invalid argument provided."),
+                None => writeln!(io, "error: No register provided."),
+            },
+            "eval" => {
+                let instruction = input.trim_start().strip_prefix("eval").unwrap_or_default();
+                match parse::parse_instruction(instruction) {
+                    Ok(stmt) if stmt.registers().any(|r| r.0 > vm.max_register) => {
+                        writeln!(
+                            io,
+                            "error: Register index is larger than the maximum '{}'.",
+                            vm.max_register
+                        )
+                    }
+                    Ok(stmt) => return Some(VmInstruction::Eval(stmt)),
+                    Err(why) => writeln!(io, "{}", why),
+                }
+            }
+            "patch" => {
+                let args = input.trim_start().strip_prefix("patch").unwrap_or_default();
+                let (line_number, instruction) = args
+                    .trim_start()
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((args.trim(), ""));
+                if line_number.is_empty() {
+                    writeln!(io, "error: No line number provided.");
+                } else if let Some(stmt_idx) = line_statement(io, vm, line_number) {
+                    let span = vm.span[stmt_idx.0];
+                    match parse::parse_patch(
+                        instruction,
+                        span,
+                        stmt_idx,
+                        &vm.span,
+                        vm.code_lines.len(),
+                        &vm.labels,
+                        vm.line_base,
+                    ) {
+                        Ok(stmt) if stmt.registers().any(|r| r.0 > vm.max_register) => writeln!(
+                            io,
+                            "error: Register index is larger than the maximum '{}'.",
+                            vm.max_register
+                        ),
+                        Ok(stmt) => {
+                            return Some(VmInstruction::Patch(
+                                stmt_idx,
+                                stmt,
+                                instruction.trim().to_owned(),
+                            ))
+                        }
+                        Err(why) => writeln!(io, "{}", why),
+                    }
+                }
+            }
+            "format" => match iter.next().and_then(RegisterFormat::parse) {
+                Some(format) => return Some(VmInstruction::Format(format)),
+                None => writeln!(io, "error: Expected 'dec', 'hex' or 'bin'."),
+            },
+            "live" => match iter.next() {
+                Some("on") => return Some(VmInstruction::LiveRegisters(true)),
+                Some("off") => return Some(VmInstruction::LiveRegisters(false)),
+                _ => writeln!(io, "error: Expected 'on' or 'off'."),
+            },
+            "stepdiff" => match iter.next() {
+                Some("on") => return Some(VmInstruction::StepDiff(true)),
+                Some("off") => return Some(VmInstruction::StepDiff(false)),
+                _ => writeln!(io, "error: Expected 'on' or 'off'."),
+            },
+            "stepshow" => match iter.next() {
+                Some("on") => return Some(VmInstruction::StepShow(true)),
+                Some("off") => return Some(VmInstruction::StepShow(false)),
+                _ => writeln!(io, "error: Expected 'on' or 'off'."),
+            },
+            "l" | "load" => match iter.next() {
+                Some(path) => return Some(VmInstruction::Load(path.to_owned())),
+                None => writeln!(io, "error: No file path provided to load from."),
+            },
+            "switch" => match iter.next() {
+                Some(name) => return Some(VmInstruction::Switch(name.to_owned())),
+                None => writeln!(io, "error: No program name provided."),
+            },
+            "title" => match iter.next() {
+                Some(name) => return Some(VmInstruction::Title(name.to_owned())),
+                None => writeln!(io, "error: No title provided."),
+            },
+            "fork" => return Some(VmInstruction::Fork),
+            "revert" => return Some(VmInstruction::Revert),
+            "q" | "quit" => return Some(VmInstruction::Stop),
+            "exit" | "quit!" => return Some(VmInstruction::Exit),
+            "export-dot" => match iter.next() {
+                Some(path) => {
+                    let graph = dot::control_flow_graph(&vm.stmts, &vm.span, vm.line_base);
+                    match std::fs::write(path, graph) {
+                        Ok(()) => writeln!(io, "Wrote control flow graph to {}.", path),
+                        Err(why) => writeln!(io, "error while writing file: {}.", why),
+                    }
+                }
+                None => writeln!(io, "error: No file path provided to export to."),
+            },
+            "dump" => match iter.next() {
+                Some(path) => match std::fs::write(path, registers_csv(vm)) {
+                    Ok(()) => writeln!(io, "Wrote registers to {}.", path),
+                    Err(why) => writeln!(io, "error while writing file: {}.", why),
+                },
+                None => writeln!(io, "error: No file path provided to dump to."),
+            },
+            "compare" => match iter.next() {
+                Some(path) => match std::fs::read_to_string(path) {
+                    Ok(content) => compare_registers(io, vm, &content),
+                    Err(why) => writeln!(io, "error while reading file: {}.", why),
+                },
+                None => writeln!(io, "error: No file path provided to compare with."),
+            },
+            "source" => match iter.next() {
+                Some(path) => match std::fs::read_to_string(path) {
+                    Ok(content) => {
+                        for line in content.lines().rev() {
+                            input_queue.push_front((line.to_owned(), Queued::Script));
+                        }
+                    }
+                    Err(why) => writeln!(io, "error while reading file: {}.", why),
+                },
+                None => writeln!(io, "error: No file path provided to source from."),
+            },
+            comment if comment.starts_with('#') => {}
+            cmd => {
+                writeln!(io, "error: Unknown command: {}.", cmd);
+                if strict {
+                    return Some(VmInstruction::Abort);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The statement on a line given as a command argument, or the next one if the line has none
+fn line_statement(io: &mut Io, vm: &Vm, line_number: &str) -> Option<StmtIdx> {
+    let line_number = match parse::parse_number::<usize>(line_number) {
+        Ok(line_number) => line_number,
+        Err(_) => {
+            writeln!(io, "error: Invalid argument provided.");
+            return None;
+        }
+    };
+    let span = match vm.span_of(line_number) {
+        Some(span) if span.0 < vm.code_lines.len() => span,
+        _ => {
+            writeln!(
+                io,
+                "error: Line number '{}' out of bounds for length {}.",
+                line_number,
+                vm.code_lines.len()
+            );
+            return None;
+        }
+    };
+    let stmt_pos = match vm.statement_at_span(span) {
+        Some(pos) => pos,
+        None => {
+            writeln!(
+                io,
+                "error: No statement on or after line '{}'.",
+                line_number
+            );
+            return None;
+        }
+    };
+    let stmt_span = vm.span[stmt_pos.0];
+    if stmt_span != span {
+        writeln!(
+            io,
+            "Line {} has no statement, using line {}.",
+            line_number,
+            vm.line(stmt_span)
+        );
+    }
+    Some(stmt_pos)
+}
+
+/// A breakpoint at `stmt`, or a change breakpoint if the arguments are `if-changed <register>`
+fn break_instruction<'a>(
+    io: &mut Io,
+    vm: &Vm,
+    stmt: StmtIdx,
+    iter: &mut impl Iterator<Item = &'a str>,
+) -> Option<VmInstruction> {
+    match (iter.next(), iter.next().map(parse::parse_number::<usize>)) {
+        (None, _) => return Some(VmInstruction::Break(stmt)),
+        (Some("if-changed"), Some(Ok(r))) if r < vm.registers.len() => {
+            return Some(VmInstruction::BreakIfChanged(stmt, Register(r)))
+        }
+        (Some("if-changed"), Some(Ok(r))) => {
+            writeln!(io, "error: Register '{}' out of bounds.", r)
+        }
+        (Some("count"), Some(Ok(count))) if count > 0 => {
+            return Some(VmInstruction::BreakCount(stmt, count as u64))
+        }
+        _ => writeln!(
+            io,
+            "error: Expected 'if-changed <register>' or 'count <n>' with n above zero."
+        ),
+    }
+    None
+}
+
+fn parse_set_command<'a>(
+    iter: &mut impl Iterator<Item = &'a str>,
+    signed: bool,
+) -> Option<(Register, usize)> {
+    let reg = iter.next().and_then(|reg| parse::parse_number(reg).ok())?;
+    let value = iter.next().and_then(|value| parse_value(value, signed))?;
+    Some((Register(reg), value))
+}
+
+/// Parses a register, a range like `0..3` or `0..=2`, or a list like `0,1,4`.
+/// Registers above `max_register` are rejected, like in programs
+fn parse_register_list(text: &str, max_register: usize) -> Option<Vec<Register>> {
+    let registers = if let Some((start, end)) = text.split_once("..") {
+        let start = parse::parse_number::<usize>(start).ok()?;
+        let end = match end.strip_prefix('=') {
+            Some(end) => parse::parse_number::<usize>(end).ok()?,
+            None => parse::parse_number::<usize>(end).ok()?.checked_sub(1)?,
+        };
+        if start > end || end > max_register {
+            return None;
+        }
+        (start..=end).map(Register).collect()
+    } else {
+        text.split(',')
+            .map(|r| parse::parse_number(r).ok().map(Register))
+            .collect::<Option<Vec<_>>>()?
+    };
+    registers
+        .iter()
+        .all(|r| r.0 <= max_register)
+        .then_some(registers)
+}
+
+/// Parses a register value, which can be negative if registers are signed
+fn parse_value(value: &str, signed: bool) -> Option<usize> {
+    if signed {
+        parse::parse_number::<isize>(value)
+            .ok()
+            .map(|value| value as usize)
+    } else {
+        parse::parse_number(value).ok()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum EditOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+/// Parses `<register> <+=|-=|*=> <value>`
+fn parse_edit_command<'a>(
+    iter: &mut impl Iterator<Item = &'a str>,
+    signed: bool,
+) -> Option<(Register, EditOp, usize)> {
+    let reg = iter.next().and_then(|reg| parse::parse_number(reg).ok())?;
+    let op = match iter.next()? {
+        "+=" => EditOp::Add,
+        "-=" => EditOp::Sub,
+        "*=" => EditOp::Mul,
+        _ => return None,
+    };
+    let amount = iter.next().and_then(|amount| parse_value(amount, signed))?;
+    Some((Register(reg), op, amount))
+}
+
+/// Applies an edit to a register value. Unsigned registers saturate, so `-=` stops at zero
+fn edit_register(vm: &Vm, value: usize, op: EditOp, amount: usize) -> usize {
+    if vm.signed {
+        let (value, amount) = (value as isize, amount as isize);
+        let edited = match op {
+            EditOp::Add => value.wrapping_add(amount),
+            EditOp::Sub => value.wrapping_sub(amount),
+            EditOp::Mul => value.wrapping_mul(amount),
+        };
+        edited as usize
+    } else {
+        match op {
+            EditOp::Add => value.saturating_add(amount),
+            EditOp::Sub => value.saturating_sub(amount),
+            EditOp::Mul => value.saturating_mul(amount),
+        }
+    }
+}
+
+fn max_register(stmts: &[Stmt]) -> usize {
+    stmts
+        .iter()
+        .flat_map(Stmt::registers)
+        .map(|r| r.0)
+        .max()
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Copy, Clone)]
+enum RegisterFormat {
+    Decimal,
+    Hex,
+    Binary,
+}
+
+impl RegisterFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "dec" => Some(RegisterFormat::Decimal),
+            "hex" => Some(RegisterFormat::Hex),
+            "bin" => Some(RegisterFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum RegisterFilter {
+    All,
+    NonZero,
+    /// An inclusive range of registers
+    Range(Register, Register),
+    /// The listed registers, in index order
+    Only(Vec<Register>),
+}
+
+fn print_registers(io: &mut Io, vm: &Vm, filter: RegisterFilter, format: RegisterFormat) {
+    writeln!(io, "Registers:");
+    for (i, r) in vm.registers.iter().enumerate() {
+        let shown = match &filter {
+            RegisterFilter::All => true,
+            RegisterFilter::NonZero => *r != 0,
+            RegisterFilter::Range(start, end) => (start.0..=end.0).contains(&i),
+            RegisterFilter::Only(registers) => registers.contains(&Register(i)),
+        };
+        if !shown {
+            continue;
+        }
+        writeln!(io, "{: >4} : {}", i, format_value(vm, *r, format));
+    }
+}
+
+/// One `register,value` row per register, followed by `pc` and `line` rows
+fn registers_csv(vm: &Vm) -> String {
+    let mut csv = String::from("register,value\n");
+    for (i, r) in vm.registers.iter().enumerate() {
+        csv.push_str(&format!(
+            "{},{}\n",
+            i,
+            format_value(vm, *r, RegisterFormat::Decimal)
+        ));
+    }
+    csv.push_str(&format!("pc,{}\n", vm.pc.0));
+    if let Some(span) = vm.span.get(vm.pc.0) {
+        csv.push_str(&format!("line,{}\n", vm.line(*span)));
+    }
+    csv
+}
+
+/// An object with `pc`, `line` and `registers`, the line is `null` at the end of the program
+fn registers_json(vm: &Vm) -> String {
+    let registers = vm
+        .registers
+        .iter()
+        .map(|r| format_value(vm, *r, RegisterFormat::Decimal))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let line = match vm.span.get(vm.pc.0) {
+        Some(span) => vm.line(*span).to_string(),
+        None => "null".to_owned(),
+    };
+    format!(
+        "{{\"pc\": {}, \"line\": {}, \"registers\": [{}]}}\n",
+        vm.pc.0, line, registers
+    )
+}
+
+/// Compares the registers against `index value` lines, also accepting the CSV written by `dump`.
+/// Rows that don't start with a register index, like the header or `pc`, are skipped.
+fn compare_registers(io: &mut Io, vm: &Vm, expected: &str) {
+    let mut compared = 0;
+    let mut mismatches = 0;
+    for (i, line) in expected.lines().enumerate() {
+        let mut fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty());
+        let register = match fields.next().map(parse::parse_number::<usize>) {
+            Some(Ok(register)) => register,
+            _ => continue,
+        };
+        let value = match fields.next().map(|value| {
+            parse::parse_number::<usize>(value)
+                .or_else(|_| parse::parse_number::<isize>(value).map(|value| value as usize))
+        }) {
+            Some(Ok(value)) => value,
+            _ => {
+                writeln!(io, "error: Invalid value on line {} of the file.", i + 1);
+                return;
+            }
+        };
+        compared += 1;
+        match vm.registers.get(register) {
+            Some(&actual) if actual == value => {}
+            Some(&actual) => {
+                mismatches += 1;
+                writeln!(
+                    io,
+                    "Register {}: expected {}, found {}.",
+                    register,
+                    format_register(vm, value),
+                    format_register(vm, actual)
+                );
+            }
+            None => {
+                mismatches += 1;
+                writeln!(
+                    io,
+                    "Register {}: expected {}, but it does not exist.",
+                    register,
+                    format_register(vm, value)
+                );
+            }
+        }
+    }
+    if mismatches == 0 {
+        writeln!(io, "Pass: all {} registers match.", compared);
+    } else {
+        writeln!(io, "Fail: {} of {} registers differ.", mismatches, compared);
+    }
+}
+
+/// Formats a register value in the format set with `format`
+fn format_register(vm: &Vm, value: usize) -> String {
+    format_value(vm, value, vm.register_format)
+}
+
+/// Formats a register value. Hex and binary show the raw bits, also for negative signed values
+fn format_value(vm: &Vm, value: usize, format: RegisterFormat) -> String {
+    match format {
+        RegisterFormat::Decimal if vm.signed => (value as isize).to_string(),
+        RegisterFormat::Decimal => value.to_string(),
+        RegisterFormat::Hex => format!("{:#x}", value),
+        RegisterFormat::Binary => format!("{:#b}", value),
+    }
+}
+
+/// Overwrites the current terminal line with all register values
+fn paint_live_registers(io: &mut Io, vm: &Vm) {
+    let registers = vm
+        .registers
+        .iter()
+        .enumerate()
+        .map(|(i, r)| format!("{}: {}", i, format_register(vm, *r)))
+        .collect::<Vec<_>>()
+        .join("  ");
+    write!(io, "\r\x1b[K{}", registers);
+    io.flush();
+}
+
+fn print_step_diff(io: &mut Io, vm: &Vm, old_registers: &[usize]) {
+    print_register_diff(io, vm, old_registers);
+    if let Some(span) = vm.span.get(vm.pc.0) {
+        writeln!(io, "At line {}.", vm.line(*span));
+    }
+}
+
+fn print_register_diff(io: &mut Io, vm: &Vm, old_registers: &[usize]) {
+    for (i, (old, new)) in old_registers.iter().zip(&vm.registers).enumerate() {
+        if old != new {
+            writeln!(
+                io,
+                "{: >4} : {} -> {}",
+                i,
+                format_register(vm, *old),
+                format_register(vm, *new)
+            );
+        }
+    }
+}
+
+fn print_program(io: &mut Io, vm: &Vm) {
     use std::cmp::min;
 
     if let Some(span_pc) = vm.span.get(vm.pc.0) {
-        println!("Program:");
+        print_program_header(io, vm);
+
+        let lower = span_pc.0.saturating_sub(5);
+        let higher = min(vm.code_lines.len(), span_pc.0 + 6);
+
+        for line_index in lower..higher {
+            print_code_line(io, vm, Span(line_index));
+        }
+    } else {
+        writeln!(io, "Reached the end of the program.");
+    }
+}
+
+/// Like `print_program`, but only shows lines with statements, five statements around the pc
+fn print_compact_program(io: &mut Io, vm: &Vm) {
+    use std::cmp::min;
+
+    if vm.pc.0 >= vm.stmts.len() {
+        writeln!(io, "Reached the end of the program.");
+        return;
+    }
+    print_program_header(io, vm);
+
+    let lower = vm.pc.0.saturating_sub(5);
+    let higher = min(vm.stmts.len(), vm.pc.0 + 6);
+
+    for stmt_idx in lower..higher {
+        print_code_line(io, vm, vm.span[stmt_idx]);
+    }
+}
+
+/// Prints every statement with its targets resolved to line numbers.
+/// Conditional jumps also show the line they fall through to.
+/// `with_counts` adds how often each statement was executed in this session
+fn print_disasm(io: &mut Io, vm: &Vm, with_counts: bool) {
+    // statements after the first on a line also show their index, a line number means the first
+    let line = |stmt: StmtIdx| match vm.span.get(stmt.0) {
+        Some(span) if stmt.0 > 0 && vm.span[stmt.0 - 1] == *span => {
+            format!("{} (*{})", vm.line(*span), stmt.0)
+        }
+        Some(span) => vm.line(*span).to_string(),
+        None => "end".to_owned(),
+    };
+
+    for (i, (stmt, span)) in vm.stmts.iter().zip(&vm.span).enumerate() {
+        let operands = match *stmt {
+            Stmt::Inc(r) | Stmt::Dec(r) => r.0.to_string(),
+            Stmt::DecBy(r, value) => format!("{} {}", r.0, value),
+            Stmt::Swap(a, b) => format!("{} {}", a.0, b.0),
+            Stmt::Rand(r, max) => format!("{} {}", r.0, max),
+            Stmt::Read(r) => r.0.to_string(),
+            Stmt::IsZero(r, target) | Stmt::IsNotZero(r, target) => {
+                format!("{} {}", r.0, line(target))
+            }
+            Stmt::IsEq(r, value, target) => format!("{} {} {}", r.0, value, line(target)),
+            Stmt::Jump(target) | Stmt::Call(target) => line(target),
+            Stmt::Ret | Stmt::Stop => String::new(),
+        };
+        let marker = if i == vm.pc.0 { ">" } else { " " };
+        let count = if with_counts {
+            format!("{: >10}x  ", vm.exec_counts[i])
+        } else {
+            String::new()
+        };
+        let instruction = format!("{} {}", stmt.mnemonic(), operands);
+        match *stmt {
+            Stmt::IsZero(_, target) | Stmt::IsNotZero(_, target) | Stmt::IsEq(_, _, target) => {
+                writeln!(
+                    io,
+                    "{} {: >4}  {}{: <20} taken: {}, fall-through: {}",
+                    marker,
+                    vm.line(*span),
+                    count,
+                    instruction,
+                    line(target),
+                    line(StmtIdx(i + 1))
+                )
+            }
+            _ => writeln!(
+                io,
+                "{} {: >4}  {}{}",
+                marker,
+                vm.line(*span),
+                count,
+                instruction.trim_end()
+            ),
+        };
+    }
+}
+
+fn print_program_header(io: &mut Io, vm: &Vm) {
+    if vm.call_stack.is_empty() {
+        writeln!(io, "Program:");
+    } else {
+        writeln!(io, "Program (call depth {}):", vm.call_stack.len());
+    }
+}
+
+/// Prints the whole program, or the lines from `start` to `end` inclusive
+fn print_list(io: &mut Io, vm: &Vm, range: Option<(Span, Span)>) {
+    let (lower, higher) = match range {
+        Some((start, end)) => (start.0, std::cmp::min(end.0 + 1, vm.code_lines.len())),
+        None => (0, vm.code_lines.len()),
+    };
+
+    writeln!(io, "Program:");
+    for line_index in lower..higher {
+        print_code_line(io, vm, Span(line_index));
+    }
+}
+
+fn print_code_line(io: &mut Io, vm: &Vm, span: Span) {
+    let code_line = expand_indentation(&vm.code_lines[span.0]);
+    let code_line = if vm.color {
+        highlight(&code_line)
+    } else {
+        code_line
+    };
+    if vm.span.get(vm.pc.0) == Some(&span) {
+        writeln!(io, "> {}  {}", vm.line(span), code_line);
+    } else {
+        writeln!(io, "{}  {}", vm.line(span), code_line);
+    }
+}
+
+/// Replaces the indentation of a source line with spaces, a tab going to the next multiple of
+/// `TAB_WIDTH`, so lines indented with tabs and spaces line up when printed. Parsing is unaffected
+fn expand_indentation(line: &str) -> String {
+    let indent_end = line
+        .find(|c: char| !c.is_whitespace())
+        .unwrap_or(line.len());
+    let mut width = 0;
+    for c in line[..indent_end].chars() {
+        width = if c == '\t' {
+            (width / TAB_WIDTH + 1) * TAB_WIDTH
+        } else {
+            width + 1
+        };
+    }
+    format!("{}{}", " ".repeat(width), &line[indent_end..])
+}
+
+const COLOR_MNEMONIC: &str = "\x1b[34m";
+const COLOR_LABEL: &str = "\x1b[33m";
+const COLOR_COMMENT: &str = "\x1b[90m";
+const COLOR_NUMBER: &str = "\x1b[36m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+/// Colors mnemonics, labels, comments and numbers of a source line, keeping its whitespace
+fn highlight(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let token_start = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        out.push_str(&rest[..token_start]);
+        rest = &rest[token_start..];
+        if rest.starts_with(['#', ';']) {
+            out.push_str(&format!("{}{}{}", COLOR_COMMENT, rest, COLOR_RESET));
+            break;
+        }
+
+        let token_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let token = &rest[..token_end];
+        rest = &rest[token_end..];
+        let color = if parse::MNEMONICS.contains(&token) || token == "INCLUDE" {
+            COLOR_MNEMONIC
+        } else if parse::parse_number::<usize>(token).is_ok()
+            || token.starts_with(['+', '-']) && parse::parse_number::<isize>(token).is_ok()
+        {
+            // relative jump targets like `+2` and `-1` are numbers too
+            COLOR_NUMBER
+        } else if token.starts_with('"') {
+            out.push_str(token);
+            continue;
+        } else {
+            COLOR_LABEL
+        };
+        out.push_str(&format!("{}{}{}", color, token, COLOR_RESET));
+    }
+    out
+}
+
+/// `line_number` must be at least the line base
+fn print_tokens(io: &mut Io, vm: &Vm, line_number: usize) {
+    let span = Span(line_number - vm.line_base);
+    let code_line = match vm.code_lines.get(span.0) {
+        Some(code_line) => code_line,
+        None => {
+            writeln!(
+                io,
+                "error: Line number '{}' out of bounds for length {}.",
+                line_number,
+                vm.code_lines.len()
+            );
+            return;
+        }
+    };
+    let line_tokens = match parse::tokenize_line(span, code_line, vm.line_base) {
+        Ok(line_tokens) => line_tokens,
+        Err(why) => {
+            writeln!(io, "{}", why);
+            return;
+        }
+    };
+    writeln!(io, "Line {}: {}", line_number, code_line.trim());
+    if let Some(label) = line_tokens.label {
+        writeln!(io, "    Label: {}", label);
+    }
+    let tokens = line_tokens
+        .tokens
+        .iter()
+        .map(|token| format!("'{}'", token))
+        .collect::<Vec<_>>();
+    writeln!(io, "    Tokens: {}", tokens.join(" "));
+    writeln!(io, "    Parsed: {}", line_tokens.ir);
+}
+
+/// `line_number` must be at least the line base
+fn print_whatis(io: &mut Io, vm: &Vm, line_number: usize) {
+    let span = Span(line_number - vm.line_base);
+    let code_line = match vm.code_lines.get(span.0) {
+        Some(code_line) => code_line,
+        None => {
+            writeln!(
+                io,
+                "error: Line number '{}' out of bounds for length {}.",
+                line_number,
+                vm.code_lines.len()
+            );
+            return;
+        }
+    };
+    writeln!(io, "Line {}: {}", line_number, code_line.trim());
+
+    let stmt_idx = match vm.statement_at_span(span) {
+        Some(stmt_idx) if vm.span[stmt_idx.0] == span => stmt_idx,
+        _ => {
+            writeln!(io, "    No statement on this line.");
+            return;
+        }
+    };
+
+    let description = match vm.stmts[stmt_idx.0] {
+        Stmt::Inc(r) => format!("INC register {}", r.0),
+        Stmt::Dec(r) => format!("DEC register {}", r.0),
+        Stmt::DecBy(r, value) => format!("DEC register {} by {}", r.0, value),
+        Stmt::IsZero(r, target) => format!(
+            "IS_ZERO register {}, jumps to {}",
+            r.0,
+            describe_target(vm, target)
+        ),
+        Stmt::IsNotZero(r, target) => format!(
+            "IS_NOT_ZERO register {}, jumps to {}",
+            r.0,
+            describe_target(vm, target)
+        ),
+        Stmt::IsEq(r, value, target) => format!(
+            "IS_EQ register {} to {}, jumps to {}",
+            r.0,
+            format_register(vm, value),
+            describe_target(vm, target)
+        ),
+        Stmt::Swap(a, b) => format!("SWAP registers {} and {}", a.0, b.0),
+        Stmt::Rand(r, max) => format!("RAND register {} below {}", r.0, max),
+        Stmt::Read(r) => format!("READ the next input into register {}", r.0),
+        Stmt::Jump(target) => format!("JUMP to {}", describe_target(vm, target)),
+        Stmt::Call(target) => format!("CALL {}", describe_target(vm, target)),
+        Stmt::Ret => "RET to the statement after the last CALL".to_owned(),
+        Stmt::Stop => "STOP".to_owned(),
+    };
+    writeln!(io, "    Statement {}: {}", stmt_idx.0, description);
+
+    let breakpoint = if vm.breakpoints.contains(&stmt_idx) {
+        "set"
+    } else {
+        "not set"
+    };
+    writeln!(io, "    Breakpoint: {}", breakpoint);
+}
+
+fn print_find(io: &mut Io, vm: &Vm, mnemonic: &str) {
+    let mnemonic = mnemonic.to_uppercase();
+    if !parse::MNEMONICS.contains(&mnemonic.as_str()) {
+        writeln!(io, "error: Unknown mnemonic: {}.", mnemonic);
+        return;
+    }
+
+    let lines = vm
+        .stmts
+        .iter()
+        .zip(&vm.span)
+        .filter(|(stmt, _)| stmt.mnemonic() == mnemonic)
+        .map(|(_, span)| vm.line(*span).to_string())
+        .collect::<Vec<_>>();
+
+    if lines.is_empty() {
+        writeln!(io, "No {} statements found.", mnemonic);
+    } else {
+        writeln!(io, "{} on lines: {}", mnemonic, lines.join(", "));
+    }
+}
+
+fn print_coverage(io: &mut Io, vm: &Vm) {
+    let uncovered = vm
+        .exec_counts
+        .iter()
+        .zip(&vm.span)
+        .filter(|(count, _)| **count == 0)
+        .map(|(_, span)| *span)
+        .collect::<Vec<_>>();
+
+    if uncovered.is_empty() {
+        writeln!(io, "All {} statements were executed.", vm.stmts.len());
+        return;
+    }
+    writeln!(
+        io,
+        "{} of {} statements were never executed:",
+        uncovered.len(),
+        vm.stmts.len()
+    );
+    for span in uncovered {
+        writeln!(
+            io,
+            "{}  {}",
+            vm.line(span),
+            expand_indentation(&vm.code_lines[span.0])
+        );
+    }
+}
+
+/// The one line summary of a session printed on `quit` with `--summary`
+fn print_summary(io: &mut Io, vm: &Vm) {
+    let instructions = vm.exec_counts.iter().sum::<u64>();
+    let position = match vm.span.get(vm.pc.0) {
+        Some(span) => format!("line {}", vm.line(*span)),
+        None => "the end".to_owned(),
+    };
+    writeln!(
+        io,
+        "Summary: {} instructions executed, {} breakpoint hits, stopped at statement {} on {}.",
+        instructions, vm.breaks, vm.pc.0, position
+    );
+}
+
+fn print_stats(io: &mut Io, vm: &Vm) {
+    writeln!(io, "Statistics for {}:", vm.file_name);
+    writeln!(io, "    Lines: {}", vm.code_lines.len());
+    writeln!(io, "    Statements: {}", vm.stmts.len());
+    for mnemonic in parse::MNEMONICS {
+        let count = vm
+            .stmts
+            .iter()
+            .filter(|stmt| stmt.mnemonic() == *mnemonic)
+            .count();
+        writeln!(io, "        {}: {}", mnemonic, count);
+    }
+    writeln!(io, "    Labels: {}", vm.labels.len());
+    writeln!(io, "    Seed: {}", vm.seed);
+    let registers = vm
+        .stmts
+        .iter()
+        .flat_map(Stmt::registers)
+        .collect::<HashSet<_>>();
+    writeln!(io, "    Registers used: {}", registers.len());
+}
+
+/// Prints the `count` most executed statements, most executed first
+fn print_profile(io: &mut Io, vm: &Vm, count: usize) {
+    let mut hottest = vm
+        .exec_counts
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, executions)| *executions > 0)
+        .collect::<Vec<_>>();
+    if hottest.is_empty() {
+        writeln!(io, "No statements were executed yet.");
+        return;
+    }
+    hottest.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    writeln!(io, "Most executed statements:");
+    for (stmt_idx, executions) in hottest.into_iter().take(count) {
+        let span = vm.span[stmt_idx];
+        writeln!(
+            io,
+            "{: >8}x  {}  {}",
+            executions,
+            vm.line(span),
+            expand_indentation(&vm.code_lines[span.0])
+        );
+    }
+}
+
+/// Prints the last `count` executed statements, oldest first
+fn print_history(io: &mut Io, vm: &Vm, count: usize) {
+    if vm.history.is_empty() {
+        writeln!(io, "No statements were executed yet.");
+        return;
+    }
+    let skip = vm.history.len().saturating_sub(count);
+    writeln!(io, "Last executed statements, oldest first:");
+    for stmt in vm.history.iter().skip(skip) {
+        let span = vm.span[stmt.0];
+        writeln!(
+            io,
+            "{}  {}",
+            vm.line(span),
+            expand_indentation(&vm.code_lines[span.0])
+        );
+    }
+}
+
+/// Prints `count` lines from `start`, each with the indices of its statements or what kind of line
+/// it is if it has none
+fn print_examine(io: &mut Io, vm: &Vm, start: Span, count: usize) {
+    let end = std::cmp::min(start.0.saturating_add(count), vm.code_lines.len());
+    for line in start.0..end {
+        let code_line = &vm.code_lines[line];
+        let statements = vm
+            .span
+            .iter()
+            .enumerate()
+            .filter(|(_, span)| span.0 == line)
+            .map(|(i, _)| format!("*{}", i))
+            .collect::<Vec<_>>();
+        let text = code_line.trim_start();
+        let kind = if !statements.is_empty() {
+            statements.join(" ")
+        } else if text.is_empty() {
+            "blank".to_owned()
+        } else if text.starts_with('#') || text.starts_with(';') {
+            "comment".to_owned()
+        } else if text.starts_with(".group") {
+            "group".to_owned()
+        } else if text.starts_with('.') {
+            "label".to_owned()
+        } else {
+            "no statement".to_owned()
+        };
+        writeln!(
+            io,
+            "{: >4}  {: <12}  {}",
+            vm.line(Span(line)),
+            kind,
+            expand_indentation(code_line)
+        );
+    }
+}
 
-        let lower = span_pc.0.saturating_sub(5);
-        let higher = min(vm.code_lines.len(), span_pc.0 + 6);
+/// Prints the line of every statement executed by `continue trace`, oldest first
+fn print_trace(io: &mut Io, vm: &Vm, trace: &[StmtIdx]) {
+    writeln!(io, "Trace of {} statements:", trace.len());
+    for chunk in trace.chunks(TRACE_LINE_LENGTH) {
+        let lines = chunk
+            .iter()
+            .map(|stmt| vm.line(vm.span[stmt.0]).to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(io, "    {}", lines);
+    }
+}
 
-        for line_index in lower..higher {
-            let code_line = vm.code_lines[line_index];
-            if line_index == span_pc.0 {
-                println!("> {}  {}", Span(line_index).line_number(), code_line);
-            } else {
-                println!("{}  {}", Span(line_index).line_number(), code_line);
-            }
+/// Prints where the statement at the pc jumps to, without executing it
+fn print_target(io: &mut Io, vm: &Vm) {
+    let stmt = match vm.stmts.get(vm.pc.0) {
+        Some(stmt) => *stmt,
+        None => {
+            writeln!(io, "Reached the end of the program.");
+            return;
+        }
+    };
+    match (stmt, stmt.target(), vm.call_stack.last()) {
+        (_, Some(target), _) => writeln!(
+            io,
+            "{} jumps to {}.",
+            stmt.mnemonic(),
+            describe_target(vm, target)
+        ),
+        (Stmt::Ret, _, Some(&target)) => {
+            writeln!(io, "RET returns to {}.", describe_target(vm, target))
         }
+        (Stmt::Ret, _, None) => writeln!(io, "RET has nothing to return to."),
+        _ => writeln!(io, "{} is not a jump.", stmt.mnemonic()),
+    }
+}
+
+fn describe_target(vm: &Vm, target: StmtIdx) -> String {
+    match vm.span.get(target.0) {
+        Some(span) => format!("statement {} (line {})", target.0, vm.line(*span)),
+        None => format!("statement {} (end of program)", target.0),
+    }
+}
+
+fn print_watches(io: &mut Io, vm: &Vm) {
+    if vm.value_watches.is_empty() {
+        writeln!(io, "No watchpoints set.");
+        return;
+    }
+    writeln!(io, "Watchpoints:");
+    for &(r, value) in &vm.value_watches {
+        writeln!(
+            io,
+            "    register {} reaches {}",
+            r.0,
+            format_register(vm, value)
+        );
+    }
+}
+
+fn print_watch(io: &mut Io, vm: &Vm, r: Register, value: usize) {
+    writeln!(
+        io,
+        "Register {} reached {}.",
+        r.0,
+        format_register(vm, value)
+    );
+}
+
+fn print_overflow(io: &mut Io, vm: &Vm, r: Register) {
+    print_program(io, vm);
+    writeln!(io, "error: Register {} overflowed.", r.0);
+}
+
+/// Reports whether a stepped conditional jump was taken, `value` is the register value it checked
+fn print_branch(io: &mut Io, vm: &Vm, stmt: Stmt, value: usize) {
+    let (r, target, taken) = match stmt {
+        Stmt::IsZero(r, target) => (r, target, value == 0),
+        Stmt::IsNotZero(r, target) => (r, target, value != 0),
+        Stmt::IsEq(r, expected, target) => (r, target, value == expected),
+        _ => return,
+    };
+    write!(
+        io,
+        "{}: register {} is {}, ",
+        stmt.mnemonic(),
+        r.0,
+        format_register(vm, value)
+    );
+    if taken {
+        let destination = match vm.span.get(target.0) {
+            Some(span) => format!("line {}", vm.line(*span)),
+            None => "the end of the program".to_owned(),
+        };
+        writeln!(io, "branch taken to {}.", destination);
     } else {
-        println!("Reached the end of the program.");
+        writeln!(io, "branch not taken.");
+    }
+}
+
+fn print_underflow(io: &mut Io, vm: &Vm, r: Register) {
+    print_program(io, vm);
+    writeln!(
+        io,
+        "Register {} would go below zero, paused before the instruction.",
+        r.0
+    );
+}
+
+/// Prints the current line, then the line every active `CALL` returns to, innermost first
+fn print_backtrace(io: &mut Io, vm: &Vm) {
+    let frames = std::iter::once(vm.pc).chain(vm.call_stack.iter().rev().copied());
+    writeln!(io, "Backtrace:");
+    for (depth, stmt) in frames.enumerate() {
+        match vm.span.get(stmt.0) {
+            Some(span) => writeln!(
+                io,
+                "    #{} line {}  {}",
+                depth,
+                vm.line(*span),
+                vm.code_lines[span.0].trim()
+            ),
+            None => writeln!(io, "    #{} end of program", depth),
+        }
     }
 }
 
-fn print_breakpoints(vm: &Vm) {
-    println!(
+fn print_empty_call_stack(io: &mut Io, vm: &Vm) {
+    print_program(io, vm);
+    writeln!(io, "error: RET without a CALL.");
+}
+
+fn print_no_input(io: &mut Io, vm: &Vm) {
+    print_program(io, vm);
+    writeln!(
+        io,
+        "error: No input left to read, all {} inputs were read.",
+        vm.inputs.len()
+    );
+}
+
+/// The rate is left out if the run was too short for the clock to measure
+fn print_run_time(io: &mut Io, instructions: u64, cycles: u64, elapsed: Duration) {
+    let millis = elapsed.as_secs_f64() * 1000.0;
+    let rate = if millis > 0.0 {
+        format!(" ({:.0} instr/ms)", instructions as f64 / millis)
+    } else {
+        String::new()
+    };
+    writeln!(
+        io,
+        "Vm ran {} instructions ({} cycles) in {}ms{}.",
+        instructions,
+        cycles,
+        elapsed.as_millis(),
+        rate
+    );
+}
+
+/// Lists the lines of the breakpoints
+fn print_breakpoints(io: &mut Io, vm: &Vm) {
+    let line = |stmt: StmtIdx| vm.line(vm.span[stmt.0]);
+    writeln!(
+        io,
         "Breakpoints:
     {}
     ",
         vm.breakpoints
             .iter()
-            .map(|p| p.0.to_string())
+            .map(|&p| line(p).to_string())
             .collect::<Vec<String>>()
             .join(", ")
     );
+    for breakpoint in &vm.change_breakpoints {
+        writeln!(
+            io,
+            "    {} if register {} changed",
+            line(breakpoint.stmt),
+            breakpoint.register.0
+        );
+    }
+    for breakpoint in &vm.count_breakpoints {
+        writeln!(
+            io,
+            "    {} on hit {} ({} hits so far)",
+            line(breakpoint.stmt),
+            breakpoint.count,
+            breakpoint.hits
+        );
+    }
 }
 
-fn print_load_help() {
-    println!(
+/// The file `break save` writes to, the source path with `.bp` appended
+fn breakpoint_file(vm: &Vm) -> Option<PathBuf> {
+    let path = vm.source_path.as_ref()?;
+    let mut file = path.clone().into_os_string();
+    file.push(".bp");
+    Some(PathBuf::from(file))
+}
+
+/// Reads the line numbers saved with `break save`. Lines without a statement anymore are dropped
+fn read_breakpoints(io: &mut Io, vm: &Vm, path: &Path) -> Option<Vec<StmtIdx>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(why) => {
+            writeln!(io, "error while reading file: {}.", why);
+            return None;
+        }
+    };
+    let mut breakpoints = Vec::new();
+    for line in content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        let span = parse::parse_number::<usize>(line)
+            .ok()
+            .and_then(|line_number| line_number.checked_sub(parse::DEFAULT_LINE_BASE))
+            .map(Span);
+        match span.and_then(|span| vm.span.iter().position(|&stmt_span| stmt_span == span)) {
+            Some(stmt) => breakpoints.push(StmtIdx(stmt)),
+            None => writeln!(
+                io,
+                "warning: Dropping breakpoint on line '{}', it has no statement.",
+                span.map_or_else(|| line.to_owned(), |span| vm.line(span).to_string())
+            ),
+        }
+    }
+    Some(breakpoints)
+}
+
+/// Prints how often each breakpoint was reached, count breakpoints also count hits they skipped
+fn print_breakpoint_stats(io: &mut Io, vm: &Vm) {
+    if vm.breakpoints.is_empty() && vm.count_breakpoints.is_empty() {
+        writeln!(io, "No breakpoints set.");
+        return;
+    }
+    writeln!(io, "Breakpoint hits:");
+    for stmt in &vm.breakpoints {
+        let hits = vm.breakpoint_hits.get(stmt).copied().unwrap_or_default();
+        writeln!(io, "    {}: {} hits", describe_target(vm, *stmt), hits);
+    }
+    for breakpoint in &vm.count_breakpoints {
+        writeln!(
+            io,
+            "    {} on hit {}: {} hits",
+            describe_target(vm, breakpoint.stmt),
+            breakpoint.count,
+            breakpoint.hits
+        );
+    }
+}
+
+fn print_version(io: &mut Io) {
+    writeln!(io, "m8db {}", VERSION);
+}
+
+fn print_load_help(io: &mut Io) {
+    writeln!(
+        io,
         "List of commands and their aliases:
 
     load (l) <filename> -- Load and run a program
+    check <filename> -- Checks a program for errors without loading it
     quit (q) -- Quits the program
+    version -- Shows the version of m8db
     help (h, ?) -- Shows this help page
     "
     );
 }
 
-fn print_debug_help() {
-    println!(
+fn print_debug_help(io: &mut Io) {
+    writeln!(
+        io,
         "List of commands and their aliases:
 
-    step (s) -- Steps the program forward by one step
+    step (s) (--show|--silent) -- Steps the program forward by one step, optionally overriding stepshow
+    back (step-back) (<n>) -- Undoes the last step or the last n steps, up to 1000
+    stepdiff <on|off> -- Show the changed registers after every step
+    stepshow <on|off> -- Show the program after every step
+    live <on|off> -- Repaint the registers on one line while 'continue' runs
     set <register> <value> -- Sets a register to a value
+    set <start>..<end> <value> -- Sets the registers from start to before end, use '..=' to include end
+    set <register>,<register>... <value> -- Sets all listed registers to a value
+    edit <register> <+=|-=|*=> <value> -- Changes a register relative to its value, '-=' stops at zero
+    watch value <register> <value> -- Break when a register reaches a value
+    watch -- Lists the watchpoints
+    unwatch <register> -- Removes the watchpoints on a register
+    eval <instruction> -- Runs an instruction on the registers without moving the program
     break <line> (b) -- Set a breakpoint to a line, use again to toggle
-    continue (c) (time) -- Run the program until the next breakpoint, add 'time' to display execution time
-    register (r) -- Shows the contents of the registers
+    break *<index> (b) -- Set a breakpoint to a statement index, starting at 0
+    break <line> if-changed <register> -- Break at a line only if the register changed since the line was last reached
+    break <line> count <n> -- Break at a line only the nth time it is reached
+    break stats -- Shows how often each breakpoint was reached
+    break save -- Saves the breakpoints as line numbers to the program file with '.bp' appended
+    break load -- Adds the breakpoints saved with 'break save'
+    continue (c) (time) (<n>) -- Run the program until the next breakpoint, add 'time' to display execution time
+        and a count to stop after at most n instructions
+    continue (c) trace -- Run like continue and print the line of every executed statement afterwards
+    continue (c) to <line> -- Run the program until it reaches a line or the next breakpoint
+    finish (until-return) -- Run until the current subroutine returns, or until a breakpoint
+    register (r) (nonzero (nz)) -- Shows the contents of the registers, add 'nonzero' to hide zero registers
+    register (r) <dec|hex|bin> -- Shows the registers in decimal, hex or binary, can be combined with the filters
+    format <dec|hex|bin> -- Sets how register values are shown, decimal by default
+    linebase <0|1> -- Sets whether line numbers start at 0 or 1 in commands and output, 1 by default
+    register (r) group <name> -- Shows the registers of a group declared with '.group'
+    register (r) only <list> -- Shows only the listed registers, like '0,2,5' or '1..=3'
+    register (r) diff-since-break -- Shows the registers that changed since the breakpoint before the last one,
+        or since the start if only one breakpoint was hit
+    register (r) --json-file <file> -- Writes the registers, the pc and the line to a JSON file
     program (p) -- Shows where the program currently is
+    pc -- Shows the index and line of the current statement
+    pc = <index> -- Moves the program to a statement index, starting at 0
+    program (p) compact -- Shows only the lines with statements around where the program is
+    examine (x) <line> (<count>) -- Shows count lines from a line with their statement indices or what kind of line they are
+    patch <line> <instruction> -- Replaces the statement on a line until the program is loaded again
+    disasm (--with-counts) -- Shows every statement with resolved line numbers and both successors of branches,
+        optionally with how often each statement was executed
+    backtrace (bt) -- Shows the current line and the lines the active CALLs return to
+    whatis <line> -- Describes the statement on a line
+    target -- Shows where the current statement jumps to
+    history (<n>) -- Shows the last n executed statements, 10 by default and at most 1000
+    tokens <line> -- Shows how the parser split up a line and what it parsed it to before resolving labels
+    find <mnemonic> -- Shows the lines of all statements with a mnemonic
+    coverage -- Shows the statements that were never executed in this session
+    stats -- Shows how many statements of each kind, labels and registers the program has
+    profile (top <n>) -- Shows the n most executed statements of this session, 5 by default
+    cycles -- Shows the total cost of the executed statements, set with '--costs'
+    list (ls) (<start> <end>) -- Shows the whole program, or the lines from start to end
+    dump <file> -- Writes the registers and the pc to a CSV file
+    compare <file> -- Compares the registers against a file of `index value` lines or a dump
+    export-dot <file> -- Writes the control flow graph of the program as a Graphviz DOT file
+    source <file> -- Runs the debugger commands in a file, line by line
+    load (l) <filename> -- Load another program and switch to it
+    switch <name> -- Switch to another loaded program, keeping the state of both
+    alias <name> = <command>; <command>... -- Defines a command that runs the commands in order,
+        without commands it removes the alias and without arguments it lists the aliases
+    fork -- Saves the whole state of the program, to try something and go back with 'revert'
+    revert -- Restores the state saved by the last 'fork' and removes it
+    title <name> -- Renames the current program in the prompt and for 'switch'
     quit (q) -- Stop execution of the current program
+    exit (quit!) -- Exits m8db
+    version -- Shows the version of m8db
     help (h, ?) -- Shows this help page
     "
     );
 }
 
-fn get_input(prompt: Option<&str>) -> String {
-    let mut input_buf = String::new();
+fn confirm(io: &mut Io, question: &str) -> bool {
+    write!(io, "{} [y/N] ", question);
+    matches!(io.read_line().as_deref(), Some("y" | "Y" | "yes"))
+}
+
+/// Reads a line of input after the prompt, `None` at the end of the input
+fn get_input(io: &mut Io, prompt: Option<&str>) -> Option<String> {
     match prompt {
-        None => print!("(m8db) "),
-        Some(text) => print!("(m8db - {}) ", text),
+        None => write!(io, "(m8db) "),
+        Some(text) => write!(io, "(m8db - {}) ", text),
+    }
+    io.read_line()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// An output the test can still read after it was moved into an `Io`
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedOutput {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A path in the temp directory that no other test uses
+    fn temp_path(extension: &str) -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        std::env::temp_dir().join(format!(
+            "m8db-test-{}-{}.{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed),
+            extension
+        ))
+    }
+
+    fn program_file(source: &str) -> String {
+        let path = temp_path("m8");
+        std::fs::write(&path, source).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    /// Runs the debugger on `source` with `commands` as its input, returns everything it printed,
+    /// errors included, and the exit code
+    fn session_code(config: Config, source: &str, commands: &str) -> (String, i32) {
+        let output = SharedOutput::default();
+        let mut io = Io::new(Cursor::new(commands.to_owned()), output.clone());
+        let path = program_file(source);
+        let code = start(&mut io, Some(Program::Source(path.clone())), config);
+        std::fs::remove_file(path).unwrap();
+        (output.text(), code)
+    }
+
+    fn session_with(config: Config, source: &str, commands: &str) -> String {
+        session_code(config, source, commands).0
+    }
+
+    fn session(source: &str, commands: &str) -> String {
+        let config = Config {
+            no_confirm: true,
+            ..Config::default()
+        };
+        session_with(config, source, commands)
+    }
+
+    /// Runs `source` like `--run`, returns everything it printed and the exit code
+    fn run_code(config: &Config, source: &str) -> (String, i32) {
+        let path = program_file(source);
+        let output = SharedOutput::default();
+        let code = run_program(
+            &mut Io::new(Cursor::new(Vec::new()), output.clone()),
+            &path,
+            config,
+        );
+        std::fs::remove_file(path).unwrap();
+        (output.text(), code)
+    }
+
+    fn sink() -> Io {
+        Io::new(Cursor::new(Vec::new()), std::io::sink())
+    }
+
+    /// Parses `source` into a VM like `load`, without a file
+    fn vm_with(source: &str, config: &Config) -> Vm {
+        let code = parse::parse(
+            source,
+            "test".to_owned(),
+            parse::DEFAULT_MAX_REGISTER,
+            parse::DEFAULT_LINE_BASE,
+            false,
+        )
+        .unwrap();
+        Vm::new(code, config)
+    }
+
+    fn vm(source: &str) -> Vm {
+        vm_with(source, &Config::default())
+    }
+
+    fn run_to_end(vm: &mut Vm) -> VmState {
+        vm.run(&mut sink(), VmRunKind::WithoutTime, None, RunUntil::Break)
+    }
+
+    #[test]
+    fn session_load_break_continue_quit() {
+        let output = session("INC 0\nINC 0\nINC 1\nSTOP\n", "b 3\nc\nr\nq\n");
+        assert!(output.starts_with("Loaded"));
+        assert!(output.contains("   0 : 2\n   1 : 0\n"), "{}", output);
+        assert!(
+            output.ends_with("Execution finished.\n(m8db) "),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn errors_go_to_the_output() {
+        let output = session_with(Config::default(), "INC 0\nJUMP nowhere\n", "");
+        assert!(
+            output.contains("error on line '2': Label 'nowhere' not found."),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn errors_go_to_their_own_stream() {
+        let output = SharedOutput::default();
+        let errors = SharedOutput::default();
+        let mut io = Io::new(Cursor::new("load\nload missing.m8\n"), output.clone())
+            .with_errors(errors.clone());
+        start(&mut io, None, Config::default());
+        assert!(output
+            .text()
+            .contains("error: No file path provided to load from."));
+        assert!(!output.text().contains("while reading file"));
+        assert!(errors.text().starts_with("error while reading file"));
+    }
+
+    #[test]
+    fn color_highlights_mnemonics_and_numbers() {
+        let highlighted = format!(
+            "{}INC{} {}0{}  {}# count{}",
+            COLOR_MNEMONIC, COLOR_RESET, COLOR_NUMBER, COLOR_RESET, COLOR_COMMENT, COLOR_RESET
+        );
+        assert_eq!(highlight("INC 0  # count"), highlighted);
+        let label = format!(
+            "{}JUMP{} {}loop{}",
+            COLOR_MNEMONIC, COLOR_RESET, COLOR_LABEL, COLOR_RESET
+        );
+        assert_eq!(highlight("JUMP loop"), label);
+        let relative = format!(
+            "{}IS_ZERO{} {}0{} {}-1{}",
+            COLOR_MNEMONIC, COLOR_RESET, COLOR_NUMBER, COLOR_RESET, COLOR_NUMBER, COLOR_RESET
+        );
+        assert_eq!(highlight("IS_ZERO 0 -1"), relative);
+    }
+
+    #[test]
+    fn version_prints_the_package_version() {
+        let output = session("INC 0\nSTOP\n", "version\n");
+        let expected = format!("m8db {}\n", env!("CARGO_PKG_VERSION"));
+        assert!(output.contains(&expected), "{}", output);
+    }
+
+    #[test]
+    fn check_reports_and_stays_at_the_load_prompt() {
+        let valid = program_file("INC 0\nSTOP\n");
+        let invalid = program_file("INC 0\nFOO\n");
+        let output = SharedOutput::default();
+        let commands = format!("check {}\ncheck {}\n", valid, invalid);
+        let mut io = Io::new(Cursor::new(commands), output.clone());
+        start(&mut io, None, Config::default());
+        std::fs::remove_file(valid).unwrap();
+        std::fs::remove_file(invalid).unwrap();
+        let output = output.text();
+        assert!(
+            output.contains("is valid, 2 statements.\n(m8db) "),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("error on line '2': Illegal statement: 'FOO'."),
+            "{}",
+            output
+        );
+        assert!(output.ends_with("(m8db) "), "{}", output);
+    }
+
+    #[test]
+    fn cycles_are_the_sum_of_the_instruction_costs() {
+        let mut config = Config::default();
+        config.costs.parse_overrides("IS_ZERO=3").unwrap();
+        let output = session_with(
+            config,
+            "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "b 7\nc\ncycles\n",
+        );
+        // three INCs, four IS_ZERO and three times DEC and JUMP
+        let expected = format!("{} cycles.", 3 + 4 * 3 + 3 * (1 + 2));
+        assert!(output.contains(&expected), "{}", output);
+    }
+
+    #[test]
+    fn if_changed_breaks_only_when_the_register_changed() {
+        // register 1 is incremented every third time around the loop
+        let output = session(
+            ".loop INC 0\nIS_ZERO 2 bump\nDEC 2\nJUMP loop\n.bump INC 1\nINC 2\nINC 2\nJUMP loop\n",
+            "break 1 if-changed 1\nc\nr\nc\nr\n",
+        );
+        // the first jump back only records the value
+        assert!(output.contains("   0 : 4\n   1 : 2\n"), "{}", output);
+        assert!(output.contains("   0 : 7\n   1 : 3\n"), "{}", output);
+    }
+
+    #[test]
+    fn program_from_stdin_runs_but_is_not_debugged() {
+        let output = SharedOutput::default();
+        let mut io = Io::new(Cursor::new("INC 0\nINC 0\nSTOP\n"), output.clone());
+        assert_eq!(run_program(&mut io, "-", &Config::default()), 0);
+        assert!(output.text().contains("   0 : 2\n"), "{}", output.text());
+
+        let output = SharedOutput::default();
+        let mut io = Io::new(Cursor::new("q\n"), output.clone());
+        let code = start(
+            &mut io,
+            Some(Program::Source("-".to_owned())),
+            Config::default(),
+        );
+        assert_eq!(code, 1);
+        assert_eq!(output.text(), format!("{}\n", STDIN_DEBUG_ERROR));
+    }
+
+    #[test]
+    fn registers_in_hex_and_binary() {
+        let source = format!("{}INC 1\nSTOP\n", "INC 0\n".repeat(255));
+        let output = session(&source, "b 256\nc\nr hex\nr bin\n");
+        assert!(output.contains("   0 : 0xff\n"), "{}", output);
+        assert!(output.contains("   0 : 0b11111111\n"), "{}", output);
+    }
+
+    #[test]
+    fn nested_calls_return_to_their_callers() {
+        let mut vm = vm("CALL a\nINC 0\nSTOP\n.a CALL b\nINC 1\nRET\n.b INC 2\nRET\n");
+        assert!(matches!(run_to_end(&mut vm), VmState::Stop));
+        assert_eq!(vm.registers, [1, 1, 1]);
+        assert!(vm.call_stack.is_empty());
+    }
+
+    #[test]
+    fn ret_without_a_call_has_its_own_exit_code() {
+        let path = program_file("INC 0\nRET\n");
+        let output = SharedOutput::default();
+        let mut io = Io::new(Cursor::new(Vec::new()), output.clone());
+        assert_eq!(run_program(&mut io, &path, &Config::default()), 6);
+        std::fs::remove_file(path).unwrap();
+        assert!(
+            output
+                .text()
+                .ends_with("error: RET without a CALL on line 2.\n"),
+            "{}",
+            output.text()
+        );
+    }
+
+    #[test]
+    fn backtrace_shows_a_frame_per_call() {
+        let output = session(
+            "CALL a\nINC 0\nSTOP\n.a CALL b\nINC 1\nRET\n.b INC 2\nRET\n",
+            "b 7\nc\nbt\n",
+        );
+        assert!(
+            output.contains(
+                "Backtrace:\n    #0 line 7  .b INC 2\n    #1 line 5  INC 1\n    #2 line 2  INC 0\n"
+            ),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn time_budget_stops_an_endless_loop() {
+        let config = Config {
+            time_budget: Some(Duration::from_millis(10)),
+            ..Config::default()
+        };
+        let mut vm = vm_with(".loop INC 0\nJUMP loop\n", &config);
+        assert!(matches!(run_to_end(&mut vm), VmState::TimeLimit));
+        assert!(vm.registers[0] > 0);
+    }
+
+    #[test]
+    fn live_registers_repaint_while_running() {
+        let config = Config {
+            time_budget: Some(LIVE_PAINT_INTERVAL * 3),
+            ..Config::default()
+        };
+        let mut vm = vm_with(".loop INC 0\nJUMP loop\n", &config);
+        vm.live_registers = true;
+        let output = SharedOutput::default();
+        let mut io = Io::new(Cursor::new(Vec::new()), output.clone());
+        vm.run(&mut io, VmRunKind::WithoutTime, None, RunUntil::Break);
+        let paints = output.text().matches("\r\x1b[K0: ").count();
+        assert!(paints >= 2, "{}", paints);
+    }
+
+    #[test]
+    fn jump_to_the_end_runs_out_of_bounds() {
+        let mut vm = vm("INC 0\nJUMP end\nINC 1\n.end\n");
+        assert!(matches!(run_to_end(&mut vm), VmState::OutOfBounds));
+        assert_eq!(vm.registers, [1, 0]);
+    }
+
+    #[test]
+    fn finish_stops_after_the_call() {
+        let output = session(
+            "CALL a\nINC 0\nSTOP\n.a CALL b\nINC 1\nRET\n.b INC 2\nRET\n",
+            "b 7\nc\nfinish\nbt\nr\n",
+        );
+        // back in `a` before its INC
+        assert!(
+            output.contains("Backtrace:\n    #0 line 5  INC 1\n    #1 line 2  INC 0\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains("   1 : 0\n   2 : 1\n"), "{}", output);
+    }
+
+    #[test]
+    fn count_breakpoint_fires_on_the_third_hit() {
+        let mut vm = vm(".loop INC 0\nJUMP loop\n");
+        vm.count_breakpoints.push(CountBreakpoint {
+            stmt: StmtIdx(1),
+            count: 3,
+            hits: 0,
+        });
+        assert!(matches!(run_to_end(&mut vm), VmState::Break));
+        assert_eq!(vm.registers[0], 3);
+        assert_eq!(vm.pc, StmtIdx(1));
+    }
+
+    #[test]
+    fn diff_since_break_shows_the_changes_of_one_iteration() {
+        let output = session(
+            ".loop INC 0\nINC 1\nINC 1\nJUMP loop\n",
+            "b 1\nc\nc\nr diff-since-break\n",
+        );
+        assert!(
+            output
+                .contains("Changes since the previous breakpoint:\n   0 : 1 -> 2\n   1 : 2 -> 4\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn diff_since_break_includes_registers_added_by_set_and_eval() {
+        let output = session(
+            ".loop INC 0\nJUMP loop\n",
+            "b 1\nc\nset 3 7\neval INC 5\nc\nr diff-since-break\n",
+        );
+        assert!(
+            output.contains(
+                "Changes since the previous breakpoint:\n   0 : 1 -> 2\n   3 : 0 -> 7\n   5 : 0 -> 1\n"
+            ),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn tokens_of_an_is_zero_line() {
+        let output = session(
+            "INC 0\n.loop IS_ZERO 0 end\nINC 1\nJUMP loop\n.end STOP\n",
+            "tokens 2\n",
+        );
+        assert!(
+            output.contains("    Tokens: 'IS_ZERO' '0' 'end'\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn set_accepts_underscores_between_digits() {
+        let output = session("INC 0\nINC 1\nSTOP\n", "set 0 1_000\nset 1 _1\nr\n");
+        assert!(output.contains("error: Invalid arguments provided."));
+        assert!(output.contains("   0 : 1000\n   1 : 0\n"), "{}", output);
+    }
+
+    #[test]
+    fn profile_lists_the_hottest_line_first() {
+        let output = session(
+            "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "b 7\nc\nprofile top 2\n",
+        );
+        assert!(
+            output.contains("Most executed statements:\n       4x  4  .loop IS_ZERO 0 end\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn unwatch_removes_one_of_two_watchpoints() {
+        let output = session(
+            "INC 0\nINC 1\nSTOP\n",
+            "watch value 0 10\nwatch value 1 7\nwatch\nunwatch 0\nwatch\n",
+        );
+        assert!(
+            output.contains("Watchpoints:\n    register 0 reaches 10\n    register 1 reaches 7\n"),
+            "{}",
+            output
+        );
+        let last = output.rsplit("Watchpoints:\n").next().unwrap();
+        assert_eq!(
+            last.lines().filter(|line| line.contains("reaches")).count(),
+            1
+        );
+        assert!(last.starts_with("    register 1 reaches 7\n"), "{}", output);
+    }
+
+    #[test]
+    fn unknown_command_aborts_only_in_strict_mode() {
+        let strict = Config {
+            strict: true,
+            ..Config::default()
+        };
+        let (output, code) = session_code(strict, "INC 0\nSTOP\n", "bogus\nr\n");
+        assert_eq!(code, 1);
+        assert!(output.contains("error: Unknown command: bogus."));
+        assert!(!output.contains("Registers:"), "{}", output);
+
+        let (output, code) = session_code(Config::default(), "INC 0\nSTOP\n", "bogus\nr\n");
+        assert_eq!(code, 0);
+        assert!(output.contains("error: Unknown command: bogus."));
+        assert!(output.contains("Registers:"), "{}", output);
+    }
+
+    #[test]
+    fn unknown_command_in_a_sourced_script_aborts_the_script() {
+        let script = program_file("set 0 5\nbogus\nset 1 7\n");
+        let commands = format!("source {}\nr\n", script);
+        let (output, code) = session_code(Config::default(), "INC 0\nINC 1\nSTOP\n", &commands);
+        assert_eq!(code, 0);
+        assert!(output.contains("error: Aborted the script after an unknown command."));
+        assert!(output.contains("   0 : 5\n   1 : 0\n"), "{}", output);
+
+        let strict = Config {
+            strict: true,
+            ..Config::default()
+        };
+        let (output, code) = session_code(strict, "INC 0\nINC 1\nSTOP\n", &commands);
+        std::fs::remove_file(script).unwrap();
+        assert_eq!(code, 1);
+        assert!(output.contains("error: Unknown command: bogus."));
+        assert!(!output.contains("Registers:"), "{}", output);
+    }
+
+    #[test]
+    fn revert_restores_the_forked_state() {
+        let output = session(
+            "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "s\nfork\ns\ns\nset 1 9\nr\nrevert\nr\nbt\n",
+        );
+        assert!(output.contains("   0 : 3\n   1 : 9\n"), "{}", output);
+        assert!(
+            output.contains("Registers:\n   0 : 1\n(m8db - m8db-test"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("Backtrace:\n    #0 line 2  INC 0\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn continue_to_a_line() {
+        let output = session(
+            "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "continue to 5\nbt\n",
+        );
+        assert!(
+            output.contains("Backtrace:\n    #0 line 5  DEC 0\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn alias_runs_its_commands_in_order() {
+        let output = session("INC 0\nINC 0\nSTOP\n", "alias two = s; r\ntwo\nbt\n");
+        let step = output.find(") s\n").unwrap();
+        let registers = output.find(") r\nRegisters:\n   0 : 1\n").unwrap();
+        assert!(step < registers, "{}", output);
+        assert!(
+            output.contains("Backtrace:\n    #0 line 2  INC 0\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn unknown_command_in_an_alias_does_not_abort() {
+        let output = session("INC 0\nINC 1\nSTOP\n", "alias odd = s; bogus; s\nodd\nr\n");
+        assert!(output.contains("error: Unknown command: bogus."));
+        assert!(!output.contains("Aborted the script"), "{}", output);
+        assert!(output.contains("   0 : 1\n   1 : 1\n"), "{}", output);
+    }
+
+    #[test]
+    fn compact_program_skips_blank_and_comment_lines() {
+        let output = session("\n; hi\nINC 0\n\n\nINC 0\n; c\nSTOP\n", "program compact\n");
+        assert!(
+            output.contains("Program:\n> 3  INC 0\n6  INC 0\n8  STOP\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn transcript_has_the_commands_and_the_output() {
+        let transcript = SharedOutput::default();
+        let path = program_file("INC 0\nSTOP\n");
+        let mut io =
+            Io::new(Cursor::new("s\nr\n"), std::io::sink()).with_transcript(transcript.clone());
+        start(
+            &mut io,
+            Some(Program::Source(path.clone())),
+            Config::default(),
+        );
+        std::fs::remove_file(path).unwrap();
+        assert!(
+            transcript
+                .text()
+                .ends_with("\ns\nr\n# Registers:\n#    0 : 1\n"),
+            "{}",
+            transcript.text()
+        );
+    }
+
+    #[test]
+    fn step_reports_whether_the_branch_was_taken() {
+        let output = session("INC 1\nIS_ZERO 1 5\nIS_ZERO 0 5\nSTOP\nSTOP\n", "s\ns\ns\n");
+        assert!(
+            output.contains("IS_ZERO: register 1 is 1, branch not taken.\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("IS_ZERO: register 0 is 0, branch taken to line 5.\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn stats_count_each_kind_of_instruction() {
+        let output = session(
+            "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "stats\n",
+        );
+        for count in [
+            "    Statements: 7\n",
+            "        INC: 3\n",
+            "        DEC: 1\n",
+            "        IS_ZERO: 1\n",
+            "        JUMP: 1\n",
+            "        STOP: 1\n",
+            "    Labels: 2\n",
+            "    Registers used: 1\n",
+        ] {
+            assert!(output.contains(count), "{}", output);
+        }
+    }
+
+    #[test]
+    fn is_not_zero_counts_down() {
+        let source = "INC 0\nINC 0\nINC 0\n.loop DEC 0\nINC 1\nIS_NOT_ZERO 0 loop\nSTOP\n";
+        let mut vm = vm(source);
+        assert!(matches!(run_to_end(&mut vm), VmState::Stop));
+        assert_eq!(vm.registers, vec![0, 3]);
+
+        // the first hit falls through to the target, the other two jump to it
+        let output = session(source, "b 4\nc\nc\nr\nc\nc\n");
+        assert!(output.contains("   0 : 2\n   1 : 1\n"), "{}", output);
+        assert_eq!(output.matches("Execution finished.").count(), 1);
+        assert!(
+            output.ends_with("Execution finished.\n(m8db) "),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn is_eq_jumps_only_on_equal_values() {
+        let mut vm = vm("INC 0\nINC 0\nIS_EQ 0 2 5\nINC 1\nIS_EQ 0 3 7\nINC 2\nSTOP\n");
+        assert!(matches!(run_to_end(&mut vm), VmState::Stop));
+        assert_eq!(vm.registers, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn target_of_a_jump() {
+        let output = session("INC 0\nJUMP end\nINC 1\n.end STOP\n", "target\ns\ntarget\n");
+        assert!(output.contains("INC is not a jump.\n"), "{}", output);
+        assert!(
+            output.contains("JUMP jumps to statement 3 (line 4).\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn set_a_range_and_a_list_of_registers() {
+        let output = session("INC 0\nINC 2\nSTOP\n", "set 0..2 7\nr\n");
+        assert!(
+            output.contains("Registers:\n   0 : 7\n   1 : 7\n   2 : 0\n"),
+            "{}",
+            output
+        );
+        let output = session("INC 0\nSTOP\n", "set 4,6 1\nr\n");
+        assert!(
+            output.contains(
+                "Registers:\n   0 : 0\n   1 : 0\n   2 : 0\n   3 : 0\n   4 : 1\n   5 : 0\n   6 : 1\n"
+            ),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn history_lists_the_executed_statements_in_order() {
+        let output = session("INC 0\nINC 1\nINC 2\nSTOP\n", "s\ns\ns\nhistory\n");
+        assert!(
+            output.contains(
+                "Last executed statements, oldest first:\n1  INC 0\n2  INC 1\n3  INC 2\n"
+            ),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn program_without_statements_is_not_loaded() {
+        let (output, code) = session_code(Config::default(), "; only\n\n; comments\n", "");
+        assert_eq!(code, 0);
+        assert!(output.starts_with(parse::NO_STATEMENTS), "{}", output);
+        assert!(output.ends_with("(m8db) "), "{}", output);
+
+        let (output, code) = run_code(&Config::default(), "; only\n\n; comments\n");
+        assert_eq!(code, 1);
+        assert_eq!(output, format!("{}\n", parse::NO_STATEMENTS));
+    }
+
+    #[test]
+    fn pc_can_be_read_and_set() {
+        let output = session(
+            "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "pc\npc = 3\npc\npc = 9\n",
+        );
+        assert!(output.contains("pc: statement 0 (line 1)"), "{}", output);
+        assert!(output.contains("pc: statement 3 (line 4)"), "{}", output);
+        assert!(
+            output.contains("error: Statement '9' out of bounds for length 7."),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn linebase_zero_shifts_the_displayed_line() {
+        let output = session("INC 0\nINC 0\nSTOP\n", "s\npc\nlinebase 0\npc\n");
+        assert!(output.contains("pc: statement 1 (line 2)"), "{}", output);
+        assert!(output.contains("pc: statement 1 (line 1)"), "{}", output);
+    }
+
+    #[test]
+    fn linebase_applies_to_breakpoints_dumps_and_parse_errors() {
+        let dump = temp_path("csv");
+        let bad = program_file("INC 0\nINC x\n");
+        let output = session(
+            "INC 0\nINC 0\nSTOP\n",
+            &format!(
+                "linebase 0\nb 1\nb\nc\ndump {}\nload {}\n",
+                dump.display(),
+                bad
+            ),
+        );
+        let csv = std::fs::read_to_string(&dump).unwrap();
+        std::fs::remove_file(dump).unwrap();
+        std::fs::remove_file(bad).unwrap();
+        assert!(output.contains("Breakpoints:\n    1\n"), "{}", output);
+        assert!(csv.ends_with("pc,1\nline,1\n"), "{}", csv);
+        assert!(output.contains("error on line '1'"), "{}", output);
+    }
+
+    #[test]
+    fn break_stats_count_the_hits() {
+        let output = session(".loop INC 0\nJUMP loop\n", "b 2\nc\nc\nc\nbreak stats\n");
+        assert!(
+            output.contains("Breakpoint hits:\n    statement 1 (line 2): 3 hits\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn tilde_expands_to_the_home_directory() {
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(
+            expand_path("~/programs/add.m8").unwrap(),
+            format!("{}/programs/add.m8", home)
+        );
+        assert_eq!(expand_path("~").unwrap(), home);
+        assert_eq!(expand_path("a~/b.m8").unwrap(), "a~/b.m8");
+    }
+
+    #[test]
+    fn run_and_assemble_expand_their_input_path() {
+        let unset = "error: Environment variable 'M8DB_TEST_UNSET' is not set.\n";
+        let errors = SharedOutput::default();
+        let mut io = Io::new(Cursor::new(Vec::new()), std::io::sink()).with_errors(errors.clone());
+        assert_eq!(
+            run_program(&mut io, "$M8DB_TEST_UNSET/add.m8", &Config::default()),
+            1
+        );
+        assert_eq!(errors.text(), unset);
+        let output = temp_path("m8b");
+        let error = assemble(
+            &mut sink(),
+            "${M8DB_TEST_UNSET}.m8",
+            output.to_str().unwrap(),
+            &Config::default(),
+        );
+        assert_eq!(error, Err(unset.trim_end().to_owned()));
+    }
+
+    #[test]
+    fn compare_reports_pass_and_the_differing_register() {
+        let matching = temp_path("csv");
+        std::fs::write(&matching, "register,value\n0,2\n1,1\n").unwrap();
+        let differing = temp_path("csv");
+        std::fs::write(&differing, "register,value\n0,2\n1,5\n").unwrap();
+        let output = session(
+            "INC 0\nINC 0\nINC 1\nSTOP\n",
+            &format!(
+                "b 4\nc\ncompare {}\ncompare {}\n",
+                matching.display(),
+                differing.display()
+            ),
+        );
+        std::fs::remove_file(matching).unwrap();
+        std::fs::remove_file(differing).unwrap();
+        assert!(
+            output.contains("Pass: all 2 registers match.\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("Register 1: expected 5, found 1.\nFail: 1 of 2 registers differ.\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn stepshow_decides_whether_a_step_shows_the_program() {
+        let output = session("INC 0\nINC 0\nINC 1\nSTOP\n", "s\n");
+        assert!(!output.contains("Program:"), "{}", output);
+        let output = session(
+            "INC 0\nINC 0\nINC 1\nSTOP\n",
+            "stepshow on\ns\ns --silent\n",
+        );
+        assert_eq!(output.matches("Program:").count(), 1, "{}", output);
+        assert!(
+            output.contains("Program:\n1  INC 0\n> 2  INC 0\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn disasm_shows_both_successors_of_is_zero() {
+        let output = session(
+            "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "disasm\n",
+        );
+        assert!(
+            output.contains("     4  IS_ZERO 0 7          taken: 7, fall-through: 5\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn saved_breakpoints_are_restored_after_reloading() {
+        let path = program_file("INC 0\nINC 0\nINC 0\nINC 0\nINC 0\nSTOP\n");
+        let commands = format!("b 2\nb 5\nbreak save\nload {}\nbreak load\nbreak\n", path);
+        let output = SharedOutput::default();
+        let mut io = Io::new(Cursor::new(commands), output.clone());
+        let config = Config {
+            no_confirm: true,
+            ..Config::default()
+        };
+        start(&mut io, Some(Program::Source(path.clone())), config);
+        std::fs::remove_file(format!("{}.bp", path)).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let output = output.text();
+        assert!(output.contains("Restored 2 breakpoints.\n"), "{}", output);
+        assert!(output.contains("Breakpoints:\n    2, 5\n"), "{}", output);
+    }
+
+    #[test]
+    fn json_file_has_pc_line_and_registers() {
+        let json = temp_path("json");
+        let output = session(
+            "INC 0\nINC 0\nINC 1\nSTOP\n",
+            &format!("b 4\nc\nr --json-file {}\n", json.display()),
+        );
+        assert!(output.contains("Wrote registers to"), "{}", output);
+        let written = std::fs::read_to_string(&json).unwrap();
+        std::fs::remove_file(json).unwrap();
+        assert_eq!(
+            written.trim_end(),
+            r#"{"pc": 3, "line": 4, "registers": [2, 1]}"#
+        );
+    }
+
+    #[test]
+    fn json_file_line_follows_linebase() {
+        let json = temp_path("json");
+        session(
+            "INC 0\nINC 0\nSTOP\n",
+            &format!("linebase 0\nb 1\nc\nr --json-file {}\n", json.display()),
+        );
+        let written = std::fs::read_to_string(&json).unwrap();
+        std::fs::remove_file(json).unwrap();
+        assert_eq!(
+            written.trim_end(),
+            r#"{"pc": 1, "line": 1, "registers": [1]}"#
+        );
+    }
+
+    #[test]
+    fn disasm_with_counts_shows_the_loop_counts() {
+        let output = session(
+            "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n",
+            "b 7\nc\ndisasm --with-counts\n",
+        );
+        assert!(
+            output.contains("     5           3x  DEC 0\n     6           3x  JUMP 4\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn back_undoes_several_steps() {
+        let output = session(
+            "INC 0\nINC 1\nINC 1\nINC 2\nINC 2\nINC 3\nSTOP\n",
+            "s\ns\ns\ns\ns\nback 3\nr\npc\n",
+        );
+        assert!(
+            output.contains("Stepped back 3 statements.\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("Registers:\n   0 : 1\n   1 : 1\n   2 : 0\n   3 : 0\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains("pc: statement 2 (line 3)"), "{}", output);
+    }
+
+    #[test]
+    fn swap_exchanges_two_registers() {
+        let mut vm = vm("INC 0\nINC 0\nINC 1\nSWAP 0 1\nSWAP 2 2\nSTOP\n");
+        assert!(matches!(run_to_end(&mut vm), VmState::Stop));
+        assert_eq!(vm.registers, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn back_undoes_a_swap() {
+        let output = session("INC 0\nSWAP 0 1\nSTOP\n", "s\ns\nback\nr\n");
+        assert!(
+            output.contains("Registers:\n   0 : 1\n   1 : 0\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn check_only_reports_each_file() {
+        let valid = program_file("INC 0\nSTOP\n");
+        let invalid = program_file("INC 0\nFOO\n");
+        let output = SharedOutput::default();
+        let mut io = Io::new(Cursor::new(Vec::new()), output.clone());
+        let code = check_programs(
+            &mut io,
+            &[valid.clone(), invalid.clone()],
+            &Config::default(),
+        );
+        std::fs::remove_file(&valid).unwrap();
+        std::fs::remove_file(&invalid).unwrap();
+        assert_eq!(code, 1);
+        let output = output.text();
+        assert!(output.contains("is valid, 2 statements.\n"), "{}", output);
+        assert!(
+            output.contains(&format!("error: {} is invalid.\n", invalid)),
+            "{}",
+            output
+        );
+        assert!(
+            output.ends_with("error: 1 of 2 programs are invalid.\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn patching_inc_to_dec_changes_the_step() {
+        let output = session(
+            "INC 0\nINC 0\nINC 0\nSTOP\n",
+            "patch 2 DEC 0\ns\ns\nr\npatch 3 JUMP nowhere\n",
+        );
+        assert!(output.contains("Patched line 2.\n"), "{}", output);
+        assert!(output.contains("Registers:\n   0 : 0\n"), "{}", output);
+        assert!(output.contains("Label 'nowhere' not found."), "{}", output);
+    }
+
+    #[test]
+    fn patch_follows_linebase_and_keeps_shared_lines() {
+        let output = session(
+            "INC 0 | INC 1\nSTOP\n",
+            "linebase 0\npatch 0 JUMP 1\nlist\ns\npc\npatch 0 FOO\n",
+        );
+        assert!(output.contains("Patched line 0.\n"), "{}", output);
+        assert!(
+            output.contains("INC 0 | INC 1 # patched *0: JUMP 1"),
+            "{}",
+            output
+        );
+        assert!(output.contains("pc: statement 2 (line 1)"), "{}", output);
+        assert!(output.contains("error on line '0'"), "{}", output);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_registers() {
+        let config = Config {
+            seed: Some(42),
+            ..Config::default()
+        };
+        let source = "RAND 0 1000\nRAND 1 1000\nRAND 0 1000\nRAND 2 1000\nSTOP\n";
+        let mut a = vm_with(source, &config);
+        let mut b = vm_with(source, &config);
+        while matches!(a.step(), VmState::Run) {
+            assert!(matches!(b.step(), VmState::Run));
+            assert_eq!(a.registers, b.registers);
+        }
+        assert_eq!(a.registers, b.registers);
+    }
+
+    #[test]
+    fn rand_loop_is_not_an_infinite_loop() {
+        let config = Config {
+            seed: Some(1),
+            detect_cycles: true,
+            ..Config::default()
+        };
+        let mut vm = vm_with(
+            ".loop RAND 0 50\nIS_EQ 0 7 end\nJUMP loop\n.end STOP\n",
+            &config,
+        );
+        assert!(matches!(run_to_end(&mut vm), VmState::Stop));
+        assert_eq!(vm.registers[0], 7);
+    }
+
+    #[test]
+    fn back_over_rand_draws_the_same_value_again() {
+        let output = session(
+            "RAND 0 1000000\nRAND 1 1000000\nSTOP\n",
+            "b 3\nc\nr\nback\ns\nr\n",
+        );
+        let registers = output
+            .split("Registers:\n")
+            .skip(1)
+            .map(|rest| rest.lines().take(2).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(registers.len(), 2, "{}", output);
+        assert_eq!(registers[0], registers[1], "{}", output);
+    }
+
+    #[test]
+    fn back_undoes_breakpoint_hits() {
+        let output = session(
+            ".loop INC 0\nIS_EQ 0 3 end\nJUMP loop\n.end STOP\n",
+            "b 2\nc\nc\nback\nbreak stats\n",
+        );
+        assert!(
+            output.contains("Breakpoint hits:\n    statement 1 (line 2): 1 hits\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn back_restores_the_value_a_change_breakpoint_compares_against() {
+        // the breakpoint fires when the loop comes back with 2, after stepping back over the
+        // JUMP it has to fire again
+        let output = session(
+            ".loop INC 0\nIS_EQ 0 3 end\nJUMP loop\n.end STOP\n",
+            "b 1 if-changed 0\nc\nback\nc\npc\n",
+        );
+        assert!(!output.contains("Execution finished"), "{}", output);
+        assert!(output.contains("pc: statement 0 (line 1)"), "{}", output);
+    }
+
+    #[test]
+    fn only_shows_the_listed_registers() {
+        let output = session(
+            "INC 0\nINC 1\nINC 2\nINC 3\nINC 3\nSTOP\n",
+            "b 6\nc\nr only 1,3\nr only 9\n",
+        );
+        assert!(
+            output.contains("Registers:\n   1 : 1\n   3 : 2\n(m8db"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("error: Register '9' out of bounds."),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn trace_has_one_entry_per_executed_statement() {
+        let source = "INC 0\nINC 0\nINC 0\n.loop IS_ZERO 0 end\nDEC 0\nJUMP loop\n.end STOP\n";
+        let output = session(source, "b 5\ncontinue --trace\ncontinue --trace\n");
+        assert!(
+            output.contains("Trace of 4 statements:\n    1 2 3 4\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("Trace of 3 statements:\n    5 6 4\n"),
+            "{}",
+            output
+        );
+
+        // 3 INC, then IS_ZERO, DEC and JUMP three times, then the last IS_ZERO and STOP
+        let output = session(source, "continue --trace\n");
+        let trace = output.split("Trace of 14 statements:\n").nth(1).unwrap();
+        assert_eq!(trace.lines().next().unwrap().split_whitespace().count(), 14);
+    }
+
+    #[test]
+    fn examine_annotates_the_kind_of_each_line() {
+        let output = session("; start\nINC 0\n\n.end\nSTOP\n", "x 1 5\n");
+        let expected = [
+            "   1  comment       ; start",
+            "   2  *0            INC 0",
+            "   3  blank         ",
+            "   4  label         .end",
+            "   5  *1            STOP",
+        ];
+        assert!(output.contains(&expected.join("\n")), "{}", output);
+    }
+
+    #[test]
+    fn read_consumes_the_inputs_in_order() {
+        let config = Config {
+            inputs: vec![3, 5],
+            ..Config::default()
+        };
+        let mut vm = vm_with("READ 0\nREAD 1\nSTOP\n", &config);
+        assert!(matches!(run_to_end(&mut vm), VmState::Stop));
+        assert_eq!(vm.registers, vec![3, 5]);
+
+        let mut vm = vm_with("READ 0\nREAD 1\nREAD 2\nSTOP\n", &config);
+        assert!(matches!(run_to_end(&mut vm), VmState::NoInput));
+    }
+
+    #[test]
+    fn read_loop_is_not_an_infinite_loop() {
+        let config = Config {
+            inputs: vec![1, 1, 1, 0],
+            detect_cycles: true,
+            ..Config::default()
+        };
+        let mut vm = vm_with(".loop READ 0\nIS_NOT_ZERO 0 loop\nSTOP\n", &config);
+        assert!(matches!(run_to_end(&mut vm), VmState::Stop));
+        assert_eq!(vm.input_position, 4);
+    }
+
+    #[test]
+    fn read_without_input_exits_with_8() {
+        let (output, code) = run_code(&Config::default(), "INC 0\nREAD 1\nSTOP\n");
+        assert_eq!(code, 8);
+        assert!(
+            output.contains("error: No input left to read on line 2."),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn back_over_read_takes_the_same_input_again() {
+        let config = Config {
+            inputs: vec![4, 6],
+            no_confirm: true,
+            ..Config::default()
+        };
+        let output = session_with(config, "READ 0\nREAD 0\nSTOP\n", "s\nback\ns\nr\n");
+        assert!(output.contains("Registers:\n   0 : 4\n"), "{}", output);
+    }
+
+    #[test]
+    fn patch_jump_to_a_line_without_a_statement_follows_linebase() {
+        let output = session(
+            "INC 0\n.end\nSTOP\n",
+            "linebase 0\npatch 0 JUMP 1\npatch 0 JUMP 3\n",
+        );
+        assert!(output.contains("line 1 has no statement"), "{}", output);
+        assert!(!output.contains("line 3 has no statement"), "{}", output);
+    }
+
+    #[test]
+    fn summary_when_quitting() {
+        let config = || Config {
+            no_confirm: true,
+            summary: true,
+            ..Config::default()
+        };
+        let output = session_with(config(), "INC 0\nINC 0\nINC 1\nSTOP\n", "b 4\nc\nq\n");
+        assert!(
+            output.contains(
+                "Summary: 3 instructions executed, 1 breakpoint hits, stopped at statement 3 on line 4.\n"
+            ),
+            "{}",
+            output
+        );
+
+        // the count and if-changed breakpoints fire once each
+        let output = session_with(
+            config(),
+            ".loop INC 0\nIS_EQ 0 3 end\nJUMP loop\n.end STOP\n",
+            "b 2 count 2\nb 1 if-changed 0\nc\nc\nq\n",
+        );
+        assert!(
+            output.contains(
+                "Summary: 6 instructions executed, 2 breakpoint hits, stopped at statement 0 on line 1.\n"
+            ),
+            "{}",
+            output
+        );
+
+        let output = session("INC 0\nINC 0\nINC 1\nSTOP\n", "b 4\nc\nq\n");
+        assert!(!output.contains("Summary:"), "{}", output);
+    }
+
+    #[test]
+    fn tab_indented_lines_line_up_with_space_indented_ones() {
+        assert_eq!(expand_indentation("\tINC 1"), "    INC 1");
+        assert_eq!(expand_indentation("  \tSTOP"), "    STOP");
+        assert_eq!(expand_indentation("    INC\t0"), "    INC\t0");
+
+        let output = session("INC 0\n\tINC 1\n  \tINC 2\n    STOP\n", "program\n");
+        assert!(
+            output.contains("2      INC 1\n3      INC 2\n4      STOP\n"),
+            "{}",
+            output
+        );
     }
-    std::io::stdout().flush().unwrap();
-    std::io::stdin().read_line(&mut input_buf).unwrap();
-    input_buf.trim().to_owned()
 }
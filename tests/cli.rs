@@ -0,0 +1,496 @@
+//! Runs the m8db binary like a user would, with the debugger commands on stdin
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long m8db may take before a test counts it as hanging
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A program written to a temp file, removed again when dropped
+struct Program(PathBuf);
+
+/// A temp file path that no other test uses
+fn temp_path(extension: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "m8db-cli-{}-{}.{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        extension
+    ))
+}
+
+impl Program {
+    fn new(source: &str) -> Self {
+        let path = temp_path("m8");
+        std::fs::write(&path, source).unwrap();
+        Program(path)
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    /// The name m8db shows for the program, its file name without the extension
+    fn name(&self) -> &str {
+        self.0.file_stem().unwrap().to_str().unwrap()
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Runs m8db with `args` and `input` on stdin, returns what it printed to stdout
+fn m8db(args: &[&str], input: &str) -> String {
+    String::from_utf8(m8db_output(args, input).stdout).unwrap()
+}
+
+/// Runs m8db with `args` and nothing on stdin, returns its stderr and exit code
+fn m8db_exit(args: &[&str]) -> (String, i32) {
+    let output = m8db_output(args, "");
+    (
+        String::from_utf8(output.stderr).unwrap(),
+        output.status.code().unwrap(),
+    )
+}
+
+fn m8db_output(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_m8db"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            panic!("m8db did not exit with the input {:?}", input);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn continue_time_counts_the_executed_instructions() {
+    // three INCs, three loop iterations of three statements and the last IS_ZERO, STOP only stops
+    let program =
+        Program::new("INC 0\nINC 0\nINC 0\n.loop\nIS_ZERO 0 end\nDEC 0\nJUMP loop\n.end\nSTOP\n");
+    let output = m8db(&[program.path()], "c time\nq\n");
+    assert!(output.contains("Vm ran 13 instructions ("), "{}", output);
+}
+
+#[test]
+fn list_prints_every_line() {
+    let program = Program::new("INC 0\nDEC 0\nSTOP\n");
+    let output = m8db(&[program.path()], "list\nquit!\n");
+    assert!(
+        output.contains("Program:\n> 1  INC 0\n2  DEC 0\n3  STOP\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn cycles_are_detected() {
+    let program = Program::new("INC 0\n.loop\nJUMP loop\n");
+    let output = m8db(&["--detect-cycles", program.path()], "c time\nquit!\n");
+    assert!(
+        output.contains("Program entered an infinite loop at line 3."),
+        "{}",
+        output
+    );
+    // the repeated JUMP that shows the cycle never ran
+    assert!(output.contains("Vm ran 2 instructions ("), "{}", output);
+}
+
+#[test]
+fn whatis_describes_is_zero() {
+    let program = Program::new("INC 0\nIS_ZERO 1 end\nSTOP\n.end\nSTOP\n");
+    let output = m8db(&[program.path()], "whatis 2\nquit!\n");
+    assert!(
+        output.contains("Statement 1: IS_ZERO register 1, jumps to statement 3 (line 5)"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn dec_by_value_stops_at_zero() {
+    let program = Program::new("INC 0\nINC 0\nINC 0\nDEC 0\nDEC 0 1\nINC 1\nDEC 1 5\nSTOP\n");
+    let output = m8db(&[program.path()], "break 8\nc\nr\nquit!\n");
+    assert!(
+        output.contains("Registers:\n   0 : 1\n   1 : 0\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn nonzero_filter_hides_zero_registers() {
+    let program = Program::new("INC 0\nDEC 0\nDEC 3\nSTOP\n");
+    let output = m8db(&[program.path()], "set 2 5\nr nonzero\nquit!\n");
+    assert!(output.contains("Registers:\n   2 : 5\n(m8db"), "{}", output);
+}
+
+#[test]
+fn step_diff_shows_the_changed_register() {
+    let program = Program::new("INC 0\nINC 1\nSTOP\n");
+    let output = m8db(&[program.path()], "set 0 4\nstepdiff on\ns\nquit!\n");
+    assert!(output.contains("   0 : 4 -> 5\n"), "{}", output);
+    assert!(!output.contains("   1 : "), "{}", output);
+}
+
+#[test]
+fn jump_to_the_first_statement() {
+    let program = Program::new("IS_ZERO 0 end\nDEC 0\nINC 1\nJUMP 1\n.end STOP\n");
+    let output = m8db(&[program.path()], "set 0 3\nbreak 5\nc\nr\nquit!\n");
+    assert!(output.contains("   1 : 3\n"), "{}", output);
+}
+
+#[test]
+fn sourced_script_acts_like_typed_commands() {
+    let script = Program::new("set 0 5\nstep\n");
+    let program = Program::new("INC 0\nINC 1\nSTOP\n");
+    let typed = m8db(&[program.path()], "set 0 5\nstep\nr\nquit!\n");
+    let sourced = m8db(
+        &[program.path()],
+        &format!("source {}\nr\nquit!\n", script.path()),
+    );
+    for output in [typed, sourced] {
+        assert!(output.contains("   0 : 6\n   1 : 0\n"), "{}", output);
+    }
+}
+
+#[test]
+fn continue_with_a_count_stops_an_endless_loop() {
+    let program = Program::new(".loop INC 0\nJUMP loop\n");
+    let output = m8db(&[program.path()], "c 10\nr\nquit!\n");
+    assert!(output.contains("Ran 10 instructions."), "{}", output);
+    assert!(output.contains("   0 : 5\n"), "{}", output);
+}
+
+#[test]
+fn group_filter_shows_only_its_registers() {
+    let program = Program::new(".group pair 1 2\nINC 0\nINC 1\nINC 2\nINC 3\nSTOP\n");
+    let output = m8db(&[program.path()], "c 4\nr group pair\nquit!\n");
+    assert!(
+        output.contains("Registers:\n   1 : 1\n   2 : 1\n(m8db - "),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn exit_code_tells_how_the_program_ended() {
+    let stops = Program::new("INC 0\nSTOP\n");
+    assert_eq!(m8db_exit(&["--run", stops.path()]).1, 0);
+    let unparsable = Program::new("INC 0\nJUMP nowhere\n");
+    assert_eq!(m8db_exit(&["--run", unparsable.path()]).1, 1);
+    let out_of_bounds = Program::new("INC 0\n");
+    let (errors, code) = m8db_exit(&["--run", out_of_bounds.path()]);
+    assert_eq!(code, 2);
+    assert!(
+        errors.ends_with("error: Program ran out of bounds.\n"),
+        "{}",
+        errors
+    );
+}
+
+#[test]
+fn options_after_run_still_apply() {
+    let looping = Program::new("INC 0\n.loop JUMP loop\n");
+    // without the option this would never exit
+    let (errors, code) = m8db_exit(&["--run", looping.path(), "--detect-cycles"]);
+    assert_eq!(code, 3);
+    assert!(errors.contains("infinite loop"), "{}", errors);
+}
+
+#[test]
+fn find_lists_the_lines_of_a_mnemonic() {
+    let program = Program::new("INC 0\n.l JUMP x\n.x INC 1\nJUMP l\n");
+    let output = m8db(&[program.path()], "find JUMP\nquit!\n");
+    assert!(output.contains("JUMP on lines: 2, 4\n"), "{}", output);
+}
+
+#[test]
+fn signed_dec_goes_below_zero() {
+    let program = Program::new(
+        "DEC 0\nIS_ZERO 0 wrong\nINC 1\nINC 0\nIS_ZERO 0 end\n.wrong INC 2\n.end STOP\n",
+    );
+    let output = m8db(
+        &["--signed", program.path()],
+        "s\nr\nbreak 7\nc\nr\nquit!\n",
+    );
+    assert!(output.contains("   0 : -1\n"), "{}", output);
+    assert!(
+        output.contains("   0 : 0\n   1 : 1\n   2 : 0\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn eval_changes_registers_but_not_the_pc() {
+    let program = Program::new("INC 1\nSTOP\n");
+    let output = m8db(&[program.path()], "eval INC 0\ns\nr\nquit!\n");
+    assert!(output.contains("   0 : 0 -> 1\n"), "{}", output);
+    // the step still runs the first statement
+    assert!(output.contains("   0 : 1\n   1 : 1\n"), "{}", output);
+
+    // an unsigned DEC on zero stays at zero instead of panicking
+    let output = m8db(&[program.path()], "eval DEC 0\nr\nquit!\n");
+    assert!(output.contains("   0 : 0\n   1 : 0\n"), "{}", output);
+}
+
+#[test]
+fn quit_unloads_and_force_quit_exits() {
+    let program = Program::new("INC 0\nSTOP\n");
+    // back at the load prompt, the second quit exits
+    let output = m8db(&[program.path()], "quit\nquit\n");
+    assert!(output.ends_with("(m8db) "), "{}", output);
+
+    let output = m8db(&[program.path()], "quit!\n");
+    assert!(!output.contains("(m8db) "), "{}", output);
+}
+
+#[test]
+fn loaded_programs_keep_their_own_pc() {
+    let first = Program::new("INC 0\nINC 0\nSTOP\n");
+    let second = Program::new("INC 1\nINC 1\nINC 1\nSTOP\n");
+    let input = format!(
+        "load {}\ns\ns\nswitch {}\ns\nr\nswitch {}\nr\nquit!\n",
+        second.path(),
+        first.name(),
+        second.name()
+    );
+    let output = m8db(&[first.path()], &input);
+    assert!(
+        output.contains(&format!("{} [1/2]) Registers:\n   0 : 1\n", first.name())),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(&format!(
+            "{} [2/2]) Registers:\n   0 : 0\n   1 : 2\n",
+            second.name()
+        )),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn dump_writes_registers_and_pc() {
+    let program = Program::new("INC 0\nINC 2\nINC 2\nSTOP\n");
+    let csv = temp_path("csv");
+    let output = m8db(
+        &[program.path()],
+        &format!("c 3\ndump {}\nquit!\n", csv.to_str().unwrap()),
+    );
+    assert!(output.contains("Wrote registers to"), "{}", output);
+    assert_eq!(
+        std::fs::read_to_string(&csv).unwrap(),
+        "register,value\n0,1\n1,0\n2,2\npc,3\nline,4\n"
+    );
+    std::fs::remove_file(csv).unwrap();
+}
+
+#[test]
+fn value_watch_breaks_when_the_value_is_reached() {
+    let program = Program::new(".loop INC 1\nINC 0\nJUMP loop\n");
+    let output = m8db(&[program.path()], "watch value 0 3\nc time\nr\nquit!\n");
+    assert!(output.contains("Register 0 reached 3."), "{}", output);
+    // the INC that reached the value ran, so it counts
+    assert!(output.contains("Vm ran 8 instructions ("), "{}", output);
+    assert!(output.contains("   0 : 3\n   1 : 3\n"), "{}", output);
+}
+
+#[test]
+fn inc_on_the_maximum_follows_the_overflow_policy() {
+    let program = Program::new("INC 0\nSTOP\n");
+    let inc_max = |policy| {
+        let input = format!("set 0 {}\ns\nr\nquit!\n", usize::MAX);
+        m8db(&["--overflow", policy, program.path()], &input)
+    };
+    assert!(inc_max("wrap").contains("   0 : 0\n"));
+    let saturated = format!("   0 : {}\n", usize::MAX);
+    assert!(inc_max("saturate").contains(&saturated));
+    let output = inc_max("error");
+    assert!(
+        output.contains("error: Register 0 overflowed."),
+        "{}",
+        output
+    );
+    assert!(output.contains(&saturated), "{}", output);
+}
+
+#[test]
+fn shebang_program_runs_like_the_program_without_it() {
+    // the shebang is line 1, so the line number jumps are one higher
+    let with_shebang =
+        Program::new("#!/usr/bin/env -S m8db --run\nINC 0\nIS_ZERO 1 5\nSTOP\nINC 0\nSTOP\n");
+    let without = Program::new("INC 0\nIS_ZERO 1 4\nSTOP\nINC 0\nSTOP\n");
+    let output = m8db_output(&["--run", with_shebang.path()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        output.stdout,
+        m8db_output(&["--run", without.path()], "").stdout
+    );
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("   0 : 2\n"),);
+}
+
+#[test]
+fn underflow_pauses_before_the_dec() {
+    let program = Program::new("INC 1\nDEC 0\nSTOP\n");
+    let output = m8db(
+        &["--break-on-underflow", program.path()],
+        "c\nr\nset 0 2\ns\nr\nquit!\n",
+    );
+    assert!(
+        output.contains("Register 0 would go below zero, paused before the instruction."),
+        "{}",
+        output
+    );
+    // the DEC did not run, the step after the fix does
+    assert!(output.contains("   0 : 0\n   1 : 1\n"), "{}", output);
+    assert!(output.contains("   0 : 1\n   1 : 1\n"), "{}", output);
+}
+
+#[test]
+fn title_renames_the_prompt() {
+    let program = Program::new("INC 0\nSTOP\n");
+    let output = m8db(&[program.path()], "title foo\nr\nquit!\n");
+    assert!(output.contains("(m8db - foo) Registers:"), "{}", output);
+}
+
+#[test]
+fn coverage_lists_the_skipped_statements() {
+    let program = Program::new("JUMP 3\nINC 1\nINC 0\nSTOP\n");
+    let output = m8db(&[program.path()], "b 4\nc\ncoverage\nquit!\n");
+    assert!(
+        output.contains("2 of 4 statements were never executed:\n2  INC 1\n4  STOP\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn break_on_a_statement_index() {
+    let program = Program::new("INC 0\n\n\n# gap\nINC 1\n\nINC 2\nSTOP\n");
+    let output = m8db(&[program.path()], "b *2\nc\nr\nquit!\n");
+    // stopped before the INC 2 on line 7
+    assert!(
+        output.contains("   0 : 1\n   1 : 1\n   2 : 0\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn edit_changes_a_register_relative_to_its_value() {
+    let program = Program::new("INC 0\nSTOP\n");
+    let output = m8db(
+        &[program.path()],
+        "edit 0 += 5\nr\nedit 0 *= 3\nr\nedit 0 -= 4\nr\nedit 0 -= 100\nr\nquit!\n",
+    );
+    let values = output
+        .lines()
+        .filter_map(|line| line.split("   0 : ").nth(1))
+        .collect::<Vec<_>>();
+    // `-=` stops at zero
+    assert_eq!(values, ["5", "15", "11", "0"]);
+}
+
+#[test]
+fn breakpoint_without_a_statement_moves_to_the_next_one() {
+    let program = Program::new("INC 0\n# comment\n\nINC 1\nSTOP\n\n");
+    let output = m8db(&[program.path()], "b 2\nb 6\nc\nr\nquit!\n");
+    assert!(
+        output.contains("Line 2 has no statement, using line 4."),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("error: No statement on or after line '6'."),
+        "{}",
+        output
+    );
+    assert!(output.contains("   0 : 1\n   1 : 0\n"), "{}", output);
+}
+
+#[test]
+fn quit_asks_only_after_a_change() {
+    let question = "Discard current debugging session? [y/N]";
+    let program = Program::new("INC 0\nSTOP\n");
+    let output = m8db(&[program.path()], "q\nq\n");
+    assert!(!output.contains(question), "{}", output);
+
+    let output = m8db(&[program.path()], "set 0 1\nq\nn\nr\nq\ny\nq\n");
+    assert_eq!(output.matches(question).count(), 2, "{}", output);
+    // still debugging after the first answer
+    assert!(output.contains("   0 : 1\n"), "{}", output);
+
+    let output = m8db(&["--no-confirm", program.path()], "set 0 1\nq\nq\n");
+    assert!(!output.contains(question), "{}", output);
+}
+
+#[test]
+fn quiet_hides_informational_messages() {
+    let program = Program::new("INC 0\nSTOP\n");
+    let output = m8db(&["--quiet", program.path()], "c\nq\n");
+    assert!(!output.contains("Loaded"), "{}", output);
+    assert!(!output.contains("Execution finished."), "{}", output);
+
+    let output = m8db_output(&["--quiet", "--run", program.path()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Registers:\n   0 : 1\n"
+    );
+}
+
+#[test]
+fn version_flag_prints_the_version_after_reading_all_flags() {
+    let output = m8db(&["--version", "--quiet"], "");
+    assert_eq!(output, format!("m8db {}\n", env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn no_banner_hides_the_banner() {
+    assert!(m8db(&[], "").starts_with("m8db - M8 Debugger\n"));
+    assert_eq!(m8db(&["--no-banner"], ""), "(m8db) ");
+}
+
+#[test]
+fn check_only_applies_earlier_flags_and_reads_stdin() {
+    let output = m8db_output(
+        &["--max-register", "2", "--check-only", "-"],
+        "INC 3\nSTOP\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let errors = String::from_utf8(output.stderr).unwrap();
+    assert!(errors.contains("error: - is invalid.\n"), "{}", errors);
+
+    let output = m8db_output(&["--check-only", "-"], "INC 3\nSTOP\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("is valid, 2 statements.\n"),);
+}